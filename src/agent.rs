@@ -0,0 +1,731 @@
+//! `agent`: an ssh-agent style daemon that holds decrypted secrets.
+//!
+//! The agent listens on a Unix domain socket and answers `get` and `search`
+//! requests, one newline-delimited JSON request and response per connection.
+//! Each fetched `SecretString` is kept in its own `mlock(2)`ed pages (never
+//! swapped, zeroed when dropped) until its lifetime runs out. Only processes
+//! running as the agent's own uid are served: the peer's uid is checked with
+//! `SO_PEERCRED` on every connection, in addition to the socket's 0600 mode.
+//!
+//! When `GOLDFINCH_AGENT_SOCK` is set, `get` and `search` go through the
+//! agent instead of calling AWS, so repeated searches reuse what it holds.
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::Notify;
+
+use crate::aws::{fetch_all_with, parse_secret_object, FetchOutcome};
+use crate::commands::secret_matches;
+use crate::embedded::{decode_embedded, Embedded};
+use crate::error::RelayedError;
+
+/// Environment variable naming the agent's socket.
+pub const SOCKET_ENV: &str = "GOLDFINCH_AGENT_SOCK";
+
+/// Longest request line the agent reads.
+const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// Longest the agent waits for a client to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often expired secrets are dropped (and so zeroed), at most.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AgentRequest {
+    Get {
+        secret: String,
+    },
+    Search {
+        pattern: String,
+        /// Match embedded JSON as `search --decode-embedded` will show it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        decode_embedded: Option<Embedded>,
+    },
+    Stop,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentResponse {
+    Secret(BTreeMap<String, Value>),
+    /// The secrets `search` would report on: those whose name or any key
+    /// path contains the pattern.
+    Secrets {
+        secrets: BTreeMap<String, BTreeMap<String, Value>>,
+        failures: Vec<(String, RelayedError)>,
+    },
+    Stopped,
+    Error(RelayedError),
+}
+
+/// Whole pages of their own, pinned in RAM with `mlock(2)` and zeroed on
+/// drop.
+///
+/// Locks are per page and not counted, so a buffer sharing a page with
+/// another would unlock its neighbour when dropped. Each buffer is therefore
+/// a separate anonymous mapping.
+struct LockedBuf {
+    ptr: *mut u8,
+    /// The mapped length, a whole number of pages.
+    cap: usize,
+    len: usize,
+}
+
+// SAFETY: the mapping is owned by this value alone and only read through
+// `&self`.
+unsafe impl Send for LockedBuf {}
+unsafe impl Sync for LockedBuf {}
+
+impl LockedBuf {
+    /// Move `value` into locked memory, zeroing the unlocked original.
+    fn from_string(value: String) -> Result<Self> {
+        let mut source = value.into_bytes();
+        let result = Self::map(source.len()).inspect(|buf| {
+            // SAFETY: the mapping holds at least `source.len()` writable
+            // bytes and does not overlap `source`.
+            unsafe { std::ptr::copy_nonoverlapping(source.as_ptr(), buf.ptr, source.len()) };
+        });
+        zero(&mut source);
+        result
+    }
+
+    /// A fresh locked mapping for `len` bytes.
+    fn map(len: usize) -> Result<Self> {
+        // SAFETY: sysconf has no preconditions.
+        let page = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).unwrap_or(4096);
+        let cap = len.max(1).div_ceil(page) * page;
+        // SAFETY: an anonymous private mapping at an address of the kernel's
+        // choosing touches no existing memory.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                cap,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            bail!(
+                "Failed to map memory for secret: {}",
+                io::Error::last_os_error()
+            );
+        }
+        let buf = LockedBuf {
+            ptr: ptr.cast(),
+            cap,
+            len,
+        };
+        // SAFETY: `buf` owns the `cap` bytes just mapped.
+        if unsafe { libc::mlock(ptr, cap) } != 0 {
+            return Err(anyhow!(
+                "Failed to lock memory for secret: {} (raise RLIMIT_MEMLOCK, e.g. `ulimit -l`)",
+                io::Error::last_os_error()
+            ));
+        }
+        Ok(buf)
+    }
+
+    fn as_str(&self) -> &str {
+        // SAFETY: the mapping holds `len` initialized bytes for as long as
+        // `self` lives.
+        let bytes = unsafe { std::slice::from_raw_parts(self.ptr, self.len) };
+        // Only ever filled from a `String`.
+        std::str::from_utf8(bytes).unwrap_or_default()
+    }
+}
+
+impl Drop for LockedBuf {
+    fn drop(&mut self) {
+        // SAFETY: `ptr` and `cap` describe the mapping made in `map`, which
+        // nothing else refers to.
+        unsafe {
+            zero(std::slice::from_raw_parts_mut(self.ptr, self.cap));
+            libc::munlock(self.ptr.cast(), self.cap);
+            libc::munmap(self.ptr.cast(), self.cap);
+        }
+    }
+}
+
+/// Overwrite `bytes` with zeros in a way the optimizer cannot elide.
+fn zero(bytes: &mut [u8]) {
+    for byte in bytes.iter_mut() {
+        // SAFETY: `byte` is a valid, aligned, exclusive reference.
+        unsafe { std::ptr::write_volatile(byte, 0) };
+    }
+    std::sync::atomic::compiler_fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Lists the account's secret names; [`crate::aws::list_all_secrets`] in
+/// production.
+pub type List = Arc<dyn Fn() -> BoxFuture<'static, Result<Vec<String>>> + Send + Sync>;
+
+/// Fetches one raw `SecretString`; [`crate::aws::fetch_secret_string`] in
+/// production.
+pub type Fetch = Arc<dyn Fn(String) -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+pub struct Agent {
+    lifetime: Duration,
+    list: List,
+    fetch: Fetch,
+    names: Mutex<Option<(Instant, Arc<Vec<String>>)>>,
+    secrets: Mutex<HashMap<String, (Instant, LockedBuf)>>,
+    stop: Notify,
+}
+
+impl Agent {
+    pub fn new(lifetime: Duration, list: List, fetch: Fetch) -> Self {
+        Agent {
+            lifetime,
+            list,
+            fetch,
+            names: Mutex::new(None),
+            secrets: Mutex::new(HashMap::new()),
+            stop: Notify::new(),
+        }
+    }
+
+    /// An agent that lists and fetches through `client`.
+    pub fn with_client(lifetime: Duration, client: aws_sdk_secretsmanager::Client) -> Self {
+        let list_client = client.clone();
+        let list: List = Arc::new(move || {
+            let client = list_client.clone();
+            Box::pin(async move { crate::aws::list_all_secrets(&client).await })
+        });
+        let fetch: Fetch = Arc::new(move |id: String| {
+            let client = client.clone();
+            Box::pin(async move { crate::aws::fetch_secret_string(&client, &id).await })
+        });
+        Agent::new(lifetime, list, fetch)
+    }
+
+    pub async fn handle(&self, request: AgentRequest) -> AgentResponse {
+        let result = match request {
+            AgentRequest::Get { secret } => self
+                .secret_string(secret)
+                .await
+                .and_then(|s| parse_secret_object(&s))
+                .map(AgentResponse::Secret),
+            AgentRequest::Search {
+                pattern,
+                decode_embedded,
+            } => self.search(&pattern, decode_embedded).await,
+            AgentRequest::Stop => {
+                self.secrets.lock().unwrap().clear();
+                self.stop.notify_one();
+                Ok(AgentResponse::Stopped)
+            }
+        };
+        result.unwrap_or_else(|err| AgentResponse::Error(RelayedError::new(&err)))
+    }
+
+    async fn search(&self, pattern: &str, embedded: Option<Embedded>) -> Result<AgentResponse> {
+        let names = self.secret_names().await?;
+        let outcome = fetch_all_with(&names, |id| async move {
+            parse_secret_object(&self.secret_string(id).await?)
+        })
+        .await?;
+        let secrets = outcome
+            .secrets
+            .into_iter()
+            .filter(|(name, data)| match embedded {
+                Some(mode) => {
+                    let mut decoded = data.clone();
+                    decode_embedded(&mut decoded, mode);
+                    secret_matches(name, &decoded, pattern)
+                }
+                None => secret_matches(name, data, pattern),
+            })
+            .collect();
+        let failures = outcome
+            .failures
+            .into_iter()
+            .map(|(id, err)| (id, RelayedError::new(&err)))
+            .collect();
+        Ok(AgentResponse::Secrets { secrets, failures })
+    }
+
+    async fn secret_names(&self) -> Result<Arc<Vec<String>>> {
+        if let Some((deadline, names)) = &*self.names.lock().unwrap() {
+            if Instant::now() < *deadline {
+                return Ok(Arc::clone(names));
+            }
+        }
+        let names = Arc::new((self.list)().await?);
+        *self.names.lock().unwrap() = Some((Instant::now() + self.lifetime, Arc::clone(&names)));
+        Ok(names)
+    }
+
+    /// The secret's `SecretString`, from locked memory when still live. The
+    /// returned copy exists only while a request is answered.
+    async fn secret_string(&self, id: String) -> Result<String> {
+        if let Some((deadline, buf)) = self.secrets.lock().unwrap().get(&id) {
+            if Instant::now() < *deadline {
+                return Ok(buf.as_str().to_string());
+            }
+        }
+        let buf = LockedBuf::from_string((self.fetch)(id.clone()).await?)?;
+        let value = buf.as_str().to_string();
+        self.secrets
+            .lock()
+            .unwrap()
+            .insert(id, (Instant::now() + self.lifetime, buf));
+        Ok(value)
+    }
+
+    /// Drop (and so zero) every secret past its lifetime.
+    fn sweep(&self) {
+        let now = Instant::now();
+        self.secrets
+            .lock()
+            .unwrap()
+            .retain(|_, (deadline, _)| now < *deadline);
+    }
+}
+
+/// The socket named by [`SOCKET_ENV`], if set.
+pub fn socket_from_env() -> Option<PathBuf> {
+    std::env::var_os(SOCKET_ENV)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// `$XDG_RUNTIME_DIR/goldfinch/agent.sock`, or a per-uid directory under the
+/// system temp directory.
+pub fn default_socket() -> PathBuf {
+    let dir = match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("goldfinch"),
+        None => std::env::temp_dir().join(format!("goldfinch-{}", euid())),
+    };
+    dir.join("agent.sock")
+}
+
+fn euid() -> u32 {
+    // SAFETY: geteuid has no preconditions and cannot fail.
+    unsafe { libc::geteuid() }
+}
+
+/// Serve `agent` on `socket` until a `stop` request arrives.
+pub async fn run(agent: Agent, socket: &Path) -> Result<()> {
+    let listener = bind(socket).await?;
+    // Keep decrypted secrets out of core dumps and away from same-uid ptrace.
+    #[cfg(target_os = "linux")]
+    // SAFETY: PR_SET_DUMPABLE takes a plain integer argument.
+    unsafe {
+        libc::prctl(libc::PR_SET_DUMPABLE, 0);
+    }
+
+    println!(
+        "{}={}; export {};",
+        SOCKET_ENV,
+        socket.display(),
+        SOCKET_ENV
+    );
+    eprintln!("agent listening on {}", socket.display());
+
+    let agent = Arc::new(agent);
+    let sweeper = {
+        let agent = Arc::clone(&agent);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(
+                SWEEP_INTERVAL
+                    .min(agent.lifetime)
+                    .max(Duration::from_secs(1)),
+            );
+            loop {
+                interval.tick().await;
+                agent.sweep();
+            }
+        })
+    };
+
+    loop {
+        tokio::select! {
+            _ = agent.stop.notified() => break,
+            accepted = listener.accept() => {
+                let stream = match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        eprintln!("warning: failed to accept connection: {err}");
+                        continue;
+                    }
+                };
+                let agent = Arc::clone(&agent);
+                tokio::spawn(async move {
+                    if let Err(err) = serve_connection(&agent, stream).await {
+                        eprintln!("warning: agent request failed: {err:#}");
+                    }
+                });
+            }
+        }
+    }
+
+    sweeper.abort();
+    let _ = std::fs::remove_file(socket);
+    Ok(())
+}
+
+/// Create the socket's directory (0700) and the socket itself (0600),
+/// replacing a stale socket but refusing to displace a live agent.
+async fn bind(socket: &Path) -> Result<UnixListener> {
+    if let Some(dir) = socket.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if !dir.exists() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+        }
+        check_private_dir(dir)?;
+    }
+    if socket.exists() {
+        if UnixStream::connect(socket).await.is_ok() {
+            bail!("An agent is already listening on '{}'", socket.display());
+        }
+        std::fs::remove_file(socket)
+            .with_context(|| format!("Failed to remove stale socket '{}'", socket.display()))?;
+    }
+    // Create the socket without group or other access, so it is never open
+    // between bind and chmod.
+    // SAFETY: umask only swaps the process's file mode mask.
+    let umask = unsafe { libc::umask(0o177) };
+    let bound = UnixListener::bind(socket);
+    // SAFETY: as above; this restores the previous mask.
+    unsafe { libc::umask(umask) };
+    let listener = bound.with_context(|| format!("Failed to listen on '{}'", socket.display()))?;
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Refuse a socket directory that another user owns or can reach: whoever
+/// controls it could replace the socket. A symlink is refused too, since
+/// its target was chosen by whoever made the link.
+fn check_private_dir(dir: &Path) -> Result<()> {
+    let metadata = std::fs::symlink_metadata(dir)
+        .with_context(|| format!("Failed to inspect '{}'", dir.display()))?;
+    if !metadata.is_dir() {
+        bail!("Refusing to use '{}': it is not a directory", dir.display());
+    }
+    if metadata.uid() != euid() {
+        bail!(
+            "Refusing to use '{}': it is owned by uid {}, not by you",
+            dir.display(),
+            metadata.uid()
+        );
+    }
+    if metadata.mode() & 0o077 != 0 {
+        bail!(
+            "Refusing to use '{}': its mode is {:o}, but other users must have no access (chmod 700)",
+            dir.display(),
+            metadata.mode() & 0o777
+        );
+    }
+    Ok(())
+}
+
+async fn serve_connection(agent: &Agent, stream: UnixStream) -> Result<()> {
+    let peer = stream.peer_cred()?.uid();
+    let (read, mut write) = stream.into_split();
+    let response = if peer != euid() {
+        eprintln!("warning: refused agent connection from uid {peer}");
+        AgentResponse::Error(RelayedError::new(&anyhow!("permission denied")))
+    } else {
+        let mut line = String::new();
+        let mut reader = BufReader::new(read).take(MAX_REQUEST_LEN);
+        tokio::time::timeout(REQUEST_TIMEOUT, reader.read_line(&mut line))
+            .await
+            .context("timed out waiting for request")??;
+        match serde_json::from_str(&line) {
+            Ok(request) => agent.handle(request).await,
+            Err(err) => {
+                AgentResponse::Error(RelayedError::new(&anyhow!("malformed request: {err}")))
+            }
+        }
+    };
+    let mut body = serde_json::to_vec(&response)?;
+    body.push(b'\n');
+    write.write_all(&body).await?;
+    write.shutdown().await?;
+    zero(&mut body);
+    Ok(())
+}
+
+/// Send one request to the agent on `socket`.
+pub async fn request(socket: &Path, request: &AgentRequest) -> Result<AgentResponse> {
+    // Only trust a socket owned by us: anyone else's socket could answer with
+    // forged secrets.
+    let metadata = std::fs::metadata(socket)
+        .with_context(|| format!("No agent socket at '{}'", socket.display()))?;
+    if metadata.uid() != euid() {
+        bail!(
+            "Agent socket '{}' is owned by uid {}, not by you",
+            socket.display(),
+            metadata.uid()
+        );
+    }
+
+    let mut stream = UnixStream::connect(socket).await.with_context(|| {
+        format!(
+            "Failed to reach the agent at '{}' (unset {} to bypass it)",
+            socket.display(),
+            SOCKET_ENV
+        )
+    })?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line).await?;
+
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply).await?;
+    serde_json::from_slice(&reply).context("Malformed response from agent")
+}
+
+/// `get` through the agent.
+pub async fn get(socket: &Path, secret: &str) -> Result<BTreeMap<String, Value>> {
+    let get = AgentRequest::Get {
+        secret: secret.to_string(),
+    };
+    match request(socket, &get).await? {
+        AgentResponse::Secret(data) => Ok(data),
+        AgentResponse::Error(err) => Err(err.into_error()),
+        other => bail!("Unexpected response from agent: {other:?}"),
+    }
+}
+
+/// The secrets relevant to a `search` for `pattern`, through the agent.
+/// Values come back as stored; `decode_embedded` only affects which secrets
+/// are relevant.
+pub async fn search(
+    socket: &Path,
+    pattern: &str,
+    decode_embedded: Option<Embedded>,
+) -> Result<FetchOutcome> {
+    let search = AgentRequest::Search {
+        pattern: pattern.to_string(),
+        decode_embedded,
+    };
+    match request(socket, &search).await? {
+        AgentResponse::Secrets { secrets, failures } => Ok(FetchOutcome {
+            secrets,
+            failures: failures
+                .into_iter()
+                .map(|(id, err)| (id, err.into_error()))
+                .collect(),
+        }),
+        AgentResponse::Error(err) => Err(err.into_error()),
+        other => bail!("Unexpected response from agent: {other:?}"),
+    }
+}
+
+/// Ask the agent on `socket` to drop its secrets and exit.
+pub async fn stop(socket: &Path) -> Result<()> {
+    match request(socket, &AgentRequest::Stop).await? {
+        AgentResponse::Stopped => Ok(()),
+        AgentResponse::Error(err) => Err(err.into_error()),
+        other => bail!("Unexpected response from agent: {other:?}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn agent(lifetime: Duration) -> (Agent, Arc<AtomicUsize>) {
+        let fetches = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&fetches);
+        let list: List = Arc::new(|| {
+            Box::pin(async {
+                Ok(vec![
+                    "prod/db".to_string(),
+                    "prod/api".to_string(),
+                    "broken".to_string(),
+                ])
+            })
+        });
+        let fetch: Fetch = Arc::new(move |id: String| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                match id.as_str() {
+                    "prod/db" => Ok(r#"{"db_password":"hunter2","host":"db"}"#.to_string()),
+                    "prod/api" => Ok(r#"{"api_key":"k","config":"{\"token\":\"t\"}"}"#.to_string()),
+                    _ => Err(anyhow::Error::new(crate::error::Error::AccessDenied {
+                        action: "secretsmanager:GetSecretValue",
+                        resource: id,
+                    })),
+                }
+            })
+        });
+        (Agent::new(lifetime, list, fetch), fetches)
+    }
+
+    #[tokio::test]
+    async fn test_get_is_served_from_memory() {
+        let (agent, fetches) = agent(Duration::from_secs(3_600));
+        for _ in 0..3 {
+            let AgentResponse::Secret(data) = agent
+                .handle(AgentRequest::Get {
+                    secret: "prod/db".to_string(),
+                })
+                .await
+            else {
+                panic!("expected a secret");
+            };
+            assert_eq!(data["db_password"], "hunter2");
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_secrets_are_refetched() {
+        let (agent, fetches) = agent(Duration::ZERO);
+        for _ in 0..2 {
+            agent
+                .handle(AgentRequest::Get {
+                    secret: "prod/db".to_string(),
+                })
+                .await;
+        }
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+        agent.sweep();
+        assert!(agent.secrets.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_returns_relevant_secrets_and_failures() {
+        let (agent, _) = agent(Duration::from_secs(3_600));
+        let AgentResponse::Secrets { secrets, failures } = agent
+            .handle(AgentRequest::Search {
+                pattern: "password".to_string(),
+                decode_embedded: None,
+            })
+            .await
+        else {
+            panic!("expected search results");
+        };
+        assert_eq!(secrets.keys().collect::<Vec<_>>(), ["prod/db"]);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, "broken");
+    }
+
+    #[tokio::test]
+    async fn test_search_matches_embedded_paths_when_asked() {
+        let (agent, _) = agent(Duration::from_secs(3_600));
+        for (decode_embedded, expected) in [(None, 0), (Some(Embedded::Json), 1)] {
+            let AgentResponse::Secrets { secrets, .. } = agent
+                .handle(AgentRequest::Search {
+                    pattern: "config.token".to_string(),
+                    decode_embedded,
+                })
+                .await
+            else {
+                panic!("expected search results");
+            };
+            assert_eq!(secrets.len(), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_round_trip_over_socket() {
+        let dir = std::env::temp_dir().join(format!("goldfinch-agent-test-{}", std::process::id()));
+        let socket = dir.join("agent.sock");
+        let (agent, _) = agent(Duration::from_secs(3_600));
+        let server = tokio::spawn({
+            let socket = socket.clone();
+            async move { run(agent, &socket).await }
+        });
+        while !socket.exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(get(&socket, "prod/api").await.unwrap()["api_key"], "k");
+        let outcome = search(&socket, "prod", None).await.unwrap();
+        assert_eq!(outcome.secrets.len(), 2);
+        assert_eq!(outcome.failures.len(), 1);
+        let err = get(&socket, "broken").await.unwrap_err();
+        assert_eq!(
+            crate::error::error_kind(&err),
+            Some(crate::error::ErrorKind::AccessDenied)
+        );
+        assert_eq!(
+            crate::error::error_kind(&outcome.failures[0].1),
+            Some(crate::error::ErrorKind::AccessDenied)
+        );
+
+        stop(&socket).await.unwrap();
+        server.await.unwrap().unwrap();
+        assert!(!socket.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shared_socket_directory_is_refused() {
+        let dir = std::env::temp_dir().join(format!("goldfinch-agent-open-{}", std::process::id()));
+        std::fs::DirBuilder::new().mode(0o755).create(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let err = bind(&dir.join("agent.sock")).await.unwrap_err();
+        assert!(err.to_string().contains("other users must have no access"));
+        assert!(!dir.join("agent.sock").exists());
+
+        let link = dir.with_extension("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        let err = bind(&link.join("agent.sock")).await.unwrap_err();
+        assert!(err.to_string().contains("not a directory"));
+        std::fs::remove_file(&link).unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_locked_buf_round_trips() {
+        let buf = LockedBuf::from_string("s3cr3t".to_string()).unwrap();
+        assert_eq!(buf.as_str(), "s3cr3t");
+        assert_eq!(LockedBuf::from_string(String::new()).unwrap().as_str(), "");
+    }
+
+    /// The `Locked:` size, in kB, of the mapping containing `addr`.
+    #[cfg(target_os = "linux")]
+    fn locked_kb(addr: usize) -> u64 {
+        let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+        let mut inside = false;
+        for line in smaps.lines() {
+            if let Some((range, _)) = line.split_once(' ').filter(|(r, _)| r.contains('-')) {
+                let (start, end) = range.split_once('-').unwrap();
+                let (start, end) = (
+                    usize::from_str_radix(start, 16).unwrap_or(0),
+                    usize::from_str_radix(end, 16).unwrap_or(0),
+                );
+                inside = (start..end).contains(&addr);
+            } else if let Some(kb) = line.strip_prefix("Locked:").filter(|_| inside) {
+                return kb.trim().trim_end_matches("kB").trim().parse().unwrap();
+            }
+        }
+        panic!("no mapping contains {addr:#x}");
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_dropping_a_buffer_keeps_its_neighbour_locked() {
+        let first = LockedBuf::from_string("first".to_string()).unwrap();
+        let second = LockedBuf::from_string("second".to_string()).unwrap();
+        assert_eq!(second.ptr as usize % second.cap, 0);
+        assert_ne!(first.ptr, second.ptr);
+        drop(first);
+        assert_eq!(second.as_str(), "second");
+        assert!(locked_kb(second.ptr as usize) > 0);
+    }
+}
@@ -1,8 +1,14 @@
 use anyhow::{bail, Context, Result};
 use aws_sdk_secretsmanager::Client;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
 use futures::stream::StreamExt;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::error::{self, Error};
+use crate::select;
 
 /// Maximum GetSecretValue calls in flight at once.
 ///
@@ -19,19 +25,76 @@ pub const MAX_CONCURRENT_FETCHES: usize = 8;
 pub const MAX_SECRETS: usize = 10_000;
 
 pub async fn fetch_secret(client: &Client, secret_id: &str) -> Result<BTreeMap<String, Value>> {
+    let secret_string = fetch_secret_string(client, secret_id).await?;
+    parse_secret_object(&secret_string)
+}
+
+/// Fetch a secret's raw `SecretString`, without requiring it to be JSON.
+///
+/// Used where a plain-text value is meaningful on its own, such as a PEM
+/// certificate stored directly as the secret.
+pub async fn fetch_secret_string(client: &Client, secret_id: &str) -> Result<String> {
+    let response = fetch_secret_value(client, secret_id, None, None).await?;
+    response
+        .secret_string
+        .context("Secret does not contain a string value")
+}
+
+/// A `GetSecretValue` response in the JSON shape the API itself (and the
+/// Secrets Manager Agent) returns.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SecretValue {
+    #[serde(rename = "ARN")]
+    pub arn: Option<String>,
+    pub name: Option<String>,
+    pub version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_string: Option<String>,
+    /// Base64, as in the API's JSON.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret_binary: Option<String>,
+    pub version_stages: Vec<String>,
+    /// Unix seconds, with a fractional part.
+    pub created_date: Option<f64>,
+}
+
+/// Fetch one version of a secret (the current one unless `version_id` or
+/// `version_stage` says otherwise).
+pub async fn fetch_secret_value(
+    client: &Client,
+    secret_id: &str,
+    version_id: Option<&str>,
+    version_stage: Option<&str>,
+) -> Result<SecretValue> {
     let response = client
         .get_secret_value()
         .secret_id(secret_id)
+        .set_version_id(version_id.map(str::to_string))
+        .set_version_stage(version_stage.map(str::to_string))
         .send()
         .await
+        .map_err(|err| error::from_sdk(err, "secretsmanager:GetSecretValue", secret_id))
         .context(format!("Failed to fetch secret '{}'", secret_id))?;
 
-    let secret_string = response
-        .secret_string()
-        .context("Secret does not contain a string value")?;
+    Ok(SecretValue {
+        arn: response.arn().map(str::to_string),
+        name: response.name().map(str::to_string),
+        version_id: response.version_id().map(str::to_string),
+        secret_string: response.secret_string().map(str::to_string),
+        secret_binary: response
+            .secret_binary()
+            .map(|blob| STANDARD.encode(blob.as_ref())),
+        version_stages: response.version_stages().to_vec(),
+        created_date: response.created_date().map(|d| d.as_secs_f64()),
+    })
+}
 
-    let json: Value =
-        serde_json::from_str(secret_string).context("Secret value is not valid JSON")?;
+/// Parse a `SecretString` as the JSON object of key-value pairs goldfinch
+/// expects.
+pub fn parse_secret_object(secret_string: &str) -> Result<BTreeMap<String, Value>> {
+    let json: Value = serde_json::from_str(secret_string)
+        .map_err(|err| anyhow::Error::new(err).context(Error::NotJson))?;
 
     match json {
         Value::Object(map) => {
@@ -41,24 +104,154 @@ pub async fn fetch_secret(client: &Client, secret_id: &str) -> Result<BTreeMap<S
             }
             Ok(btree_map)
         }
-        _ => bail!("Secret value is not a JSON object with key-value pairs"),
+        _ => bail!(Error::NotObject),
     }
 }
 
+/// Fetch the raw `SecretString` of every id with the same bounded fan-out as
+/// [`fetch_secrets_concurrent`].
+pub async fn fetch_secret_strings_concurrent(
+    client: &Client,
+    secret_ids: &[String],
+) -> Result<FetchOutcome<String>> {
+    fetch_all_with(secret_ids, |id| async move {
+        fetch_secret_string(client, &id).await
+    })
+    .await
+}
+
 pub async fn list_all_secrets(client: &Client) -> Result<Vec<String>> {
-    let mut secret_names = Vec::new();
+    let secrets = list_secret_metadata(client).await?;
+    Ok(secrets.into_iter().map(|secret| secret.name).collect())
+}
+
+/// Resolve `--secret` arguments (names or globs) to secret names.
+///
+/// ListSecrets is only called when at least one argument is a glob, so
+/// selecting secrets by exact name works under a GetSecretValue-only policy.
+pub async fn resolve_secret_names(client: &Client, patterns: &[String]) -> Result<Vec<String>> {
+    if !patterns.iter().any(|p| select::is_pattern(p)) {
+        return Ok(select::select(&[], patterns));
+    }
+    let names = list_all_secrets(client).await?;
+    Ok(select::select(&names, patterns))
+}
+
+/// Metadata about one secret, as reported by ListSecrets / DescribeSecret.
+///
+/// Nothing here is decrypted: every field is readable with `ListSecrets` (or
+/// `DescribeSecret` for `replica_regions`) alone. Timestamps are Unix seconds.
+#[derive(Debug, Clone, Default)]
+pub struct SecretMetadata {
+    pub name: String,
+    pub description: Option<String>,
+    /// `None` means the secret uses the AWS managed key `aws/secretsmanager`.
+    pub kms_key_id: Option<String>,
+    pub rotation_enabled: bool,
+    pub last_rotated: Option<i64>,
+    pub last_changed: Option<i64>,
+    pub created: Option<i64>,
+    /// Id of the version labelled `AWSCURRENT`.
+    pub current_version: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub primary_region: Option<String>,
+    /// Regions the secret is replicated to. ListSecrets does not report
+    /// replication, so this is `None` unless [`describe_secret`] filled it.
+    pub replica_regions: Option<Vec<String>>,
+}
+
+/// List every secret in the account with its metadata.
+///
+/// This is the single ListSecrets pagination path; [`list_all_secrets`] is a
+/// projection of it.
+pub async fn list_secret_metadata(client: &Client) -> Result<Vec<SecretMetadata>> {
+    let mut secrets = Vec::new();
     let mut paginator = client.list_secrets().into_paginator().send();
 
     while let Some(result) = paginator.next().await {
-        let output = result.context("Failed to list secrets")?;
-        for secret in output.secret_list() {
-            if let Some(name) = secret.name() {
-                secret_names.push(name.to_string());
-            }
+        let output = result
+            .map_err(|err| error::from_sdk(err, "secretsmanager:ListSecrets", "*"))
+            .context("Failed to list secrets")?;
+        for entry in output.secret_list() {
+            let Some(name) = entry.name() else {
+                continue;
+            };
+            secrets.push(SecretMetadata {
+                name: name.to_string(),
+                description: entry.description().map(str::to_string),
+                kms_key_id: entry.kms_key_id().map(str::to_string),
+                rotation_enabled: entry.rotation_enabled().unwrap_or(false),
+                last_rotated: entry.last_rotated_date().map(|d| d.secs()),
+                last_changed: entry.last_changed_date().map(|d| d.secs()),
+                created: entry.created_date().map(|d| d.secs()),
+                current_version: current_version(entry.secret_versions_to_stages()),
+                tags: collect_tags(entry.tags()),
+                primary_region: entry.primary_region().map(str::to_string),
+                replica_regions: None,
+            });
         }
     }
 
-    Ok(secret_names)
+    Ok(secrets)
+}
+
+/// Fetch one secret's metadata, including its replication status.
+pub async fn describe_secret(client: &Client, secret_id: &str) -> Result<SecretMetadata> {
+    let output = client
+        .describe_secret()
+        .secret_id(secret_id)
+        .send()
+        .await
+        .map_err(|err| error::from_sdk(err, "secretsmanager:DescribeSecret", secret_id))
+        .context(format!("Failed to describe secret '{}'", secret_id))?;
+
+    Ok(SecretMetadata {
+        name: output.name().unwrap_or(secret_id).to_string(),
+        description: output.description().map(str::to_string),
+        kms_key_id: output.kms_key_id().map(str::to_string),
+        rotation_enabled: output.rotation_enabled().unwrap_or(false),
+        last_rotated: output.last_rotated_date().map(|d| d.secs()),
+        last_changed: output.last_changed_date().map(|d| d.secs()),
+        created: output.created_date().map(|d| d.secs()),
+        current_version: current_version(output.version_ids_to_stages()),
+        tags: collect_tags(output.tags()),
+        primary_region: output.primary_region().map(str::to_string),
+        replica_regions: Some(
+            output
+                .replication_status()
+                .iter()
+                .filter_map(|replica| replica.region().map(str::to_string))
+                .collect(),
+        ),
+    })
+}
+
+fn current_version(versions: Option<&HashMap<String, Vec<String>>>) -> Option<String> {
+    versions?
+        .iter()
+        .find(|(_, stages)| stages.iter().any(|stage| stage == "AWSCURRENT"))
+        .map(|(id, _)| id.clone())
+}
+
+fn collect_tags(tags: &[aws_sdk_secretsmanager::types::Tag]) -> BTreeMap<String, String> {
+    tags.iter()
+        .filter_map(|tag| {
+            let key = tag.key()?;
+            Some((key.to_string(), tag.value().unwrap_or_default().to_string()))
+        })
+        .collect()
+}
+
+/// Describe every id in `secret_ids` with the same bounded fan-out as
+/// [`fetch_secrets_concurrent`].
+pub async fn describe_secrets_concurrent(
+    client: &Client,
+    secret_ids: &[String],
+) -> Result<FetchOutcome<SecretMetadata>> {
+    fetch_all_with(secret_ids, |id| async move {
+        describe_secret(client, &id).await
+    })
+    .await
 }
 
 /// The result of fetching a batch of secrets.
@@ -67,8 +260,8 @@ pub async fn list_all_secrets(client: &Client) -> Result<Vec<String>> {
 /// aborting the batch: `list_secrets` returns names the caller may not be
 /// allowed to `get`, so one unreadable secret must not deny the whole search.
 #[derive(Debug)]
-pub struct FetchOutcome {
-    pub secrets: BTreeMap<String, BTreeMap<String, Value>>,
+pub struct FetchOutcome<T = BTreeMap<String, Value>> {
+    pub secrets: BTreeMap<String, T>,
     pub failures: Vec<(String, anyhow::Error)>,
 }
 
@@ -87,10 +280,10 @@ pub async fn fetch_secrets_concurrent(
 ///
 /// Split out from [`fetch_secrets_concurrent`] so the fan-out behaviour can be
 /// exercised without an AWS client.
-pub async fn fetch_all_with<F, Fut>(secret_ids: &[String], fetch: F) -> Result<FetchOutcome>
+pub async fn fetch_all_with<T, F, Fut>(secret_ids: &[String], fetch: F) -> Result<FetchOutcome<T>>
 where
     F: Fn(String) -> Fut,
-    Fut: std::future::Future<Output = Result<BTreeMap<String, Value>>>,
+    Fut: std::future::Future<Output = Result<T>>,
 {
     if secret_ids.len() > MAX_SECRETS {
         bail!(
@@ -100,8 +293,9 @@ where
         );
     }
 
-    let mut pending = futures::stream::iter(secret_ids.iter().map(|id| {
-        let id = id.clone();
+    // Owned ids keep the mapping closure free of borrowed arguments, so the
+    // returned future stays `Send` when a caller spawns it.
+    let mut pending = futures::stream::iter(secret_ids.iter().cloned().map(|id| {
         let pending = fetch(id.clone());
         async move { (id, pending.await) }
     }))
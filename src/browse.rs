@@ -0,0 +1,475 @@
+//! `browse`: an interactive picker over secret names.
+//!
+//! Typing filters the list with the [`crate::fuzzy`] scorer. `Enter` opens a
+//! secret into its key paths, whose values stay masked until revealed one at
+//! a time. A value can then be copied to the clipboard or printed on exit.
+//!
+//! [`Browser`] holds the state and produces each frame as plain lines; every
+//! piece of secret-derived text in them goes through the escaping in
+//! [`crate::commands`], so a name, key or value cannot emit control
+//! sequences. The terminal loop only positions those lines. The UI is drawn
+//! on stderr, keeping stdout free for the printed value.
+
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::io::{self, Write};
+
+use crate::cli::{OutputFormat, PathSegment};
+use crate::clipboard;
+use crate::commands::{value_to_string, walk_leaves, Sanitized, SanitizedPath};
+use crate::fuzzy::{score, MIN_SCORE};
+
+/// Shown in place of a value that has not been revealed. Fixed-width, so it
+/// does not give away the value's length.
+pub const MASK: &str = "••••••••";
+
+/// What the terminal loop should do after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    None,
+    Quit,
+    /// Fetch this secret and pass it to [`Browser::open`].
+    Open(String),
+    /// Copy this value to the clipboard.
+    Copy(String),
+    /// Leave the UI and print this value.
+    Print(String),
+}
+
+/// An opened secret.
+struct Keys {
+    secret: String,
+    leaves: Vec<(Vec<PathSegment>, String)>,
+    selected: usize,
+    revealed: BTreeSet<usize>,
+}
+
+impl Keys {
+    /// Reveal the selected value, or hide it again.
+    fn toggle_reveal(&mut self) {
+        if !self.revealed.remove(&self.selected) {
+            self.revealed.insert(self.selected);
+        }
+    }
+}
+
+/// One frame: lines of sanitized text, and which line (if any) is
+/// highlighted.
+#[derive(Debug)]
+pub struct Screen {
+    pub lines: Vec<String>,
+    pub highlight: Option<usize>,
+}
+
+pub struct Browser {
+    names: Vec<String>,
+    query: String,
+    /// Indices into `names`, best match first.
+    matches: Vec<usize>,
+    selected: usize,
+    keys: Option<Keys>,
+    status: Option<String>,
+}
+
+impl Browser {
+    pub fn new(names: Vec<String>, query: &str) -> Self {
+        let mut browser = Browser {
+            names,
+            query: query.to_string(),
+            matches: Vec::new(),
+            selected: 0,
+            keys: None,
+            status: None,
+        };
+        browser.refilter();
+        browser
+    }
+
+    fn refilter(&mut self) {
+        self.selected = 0;
+        if self.query.is_empty() {
+            self.matches = (0..self.names.len()).collect();
+            return;
+        }
+        let mut scored: Vec<(f64, usize)> = self
+            .names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (score(&self.query, name), i))
+            .filter(|(s, _)| *s >= MIN_SCORE)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        self.matches = scored.into_iter().map(|(_, i)| i).collect();
+    }
+
+    /// The secret names currently listed, best first.
+    pub fn visible(&self) -> Vec<&str> {
+        self.matches
+            .iter()
+            .map(|&i| self.names[i].as_str())
+            .collect()
+    }
+
+    /// Show `data` as the keys of `secret`.
+    pub fn open(&mut self, secret: String, data: &BTreeMap<String, Value>) {
+        let mut leaves = Vec::new();
+        walk_leaves(data, &mut |path, value| {
+            leaves.push((path.to_vec(), value_to_string(value)));
+        });
+        self.keys = Some(Keys {
+            secret,
+            leaves,
+            selected: 0,
+            revealed: BTreeSet::new(),
+        });
+        self.status = None;
+    }
+
+    /// Show a one-line message under the list.
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    fn move_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    fn move_down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) -> Action {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl && key.code == KeyCode::Char('c') {
+            return Action::Quit;
+        }
+        self.status = None;
+        match &mut self.keys {
+            Some(keys) => {
+                let selected = keys.leaves.get(keys.selected).map(|(_, v)| v.clone());
+                match key.code {
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        keys.selected = keys.selected.saturating_sub(1)
+                    }
+                    KeyCode::Down | KeyCode::Char('j') if keys.selected + 1 < keys.leaves.len() => {
+                        keys.selected += 1
+                    }
+                    KeyCode::Char('r') | KeyCode::Char(' ') => keys.toggle_reveal(),
+                    KeyCode::Char('c') => return selected.map_or(Action::None, Action::Copy),
+                    KeyCode::Char('p') | KeyCode::Enter => {
+                        return selected.map_or(Action::None, Action::Print)
+                    }
+                    KeyCode::Esc | KeyCode::Left | KeyCode::Backspace => self.keys = None,
+                    KeyCode::Char('q') => return Action::Quit,
+                    _ => {}
+                }
+            }
+            None => match key.code {
+                KeyCode::Up => self.move_up(),
+                KeyCode::Char('p') if ctrl => self.move_up(),
+                KeyCode::Down => self.move_down(),
+                KeyCode::Char('n') if ctrl => self.move_down(),
+                KeyCode::Enter | KeyCode::Right => {
+                    if let Some(&i) = self.matches.get(self.selected) {
+                        return Action::Open(self.names[i].clone());
+                    }
+                }
+                KeyCode::Esc if self.query.is_empty() => return Action::Quit,
+                KeyCode::Esc => {
+                    self.query.clear();
+                    self.refilter();
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refilter();
+                }
+                KeyCode::Char(c) if !ctrl => {
+                    self.query.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            },
+        }
+        Action::None
+    }
+
+    /// Lay out a `width` x `height` frame.
+    pub fn render(&self, width: usize, height: usize) -> Screen {
+        let rows = height.saturating_sub(2);
+        let (header, body, selected, help) = match &self.keys {
+            Some(keys) => {
+                let body: Vec<String> = keys
+                    .leaves
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (path, value))| {
+                        let shown = if keys.revealed.contains(&i) {
+                            Sanitized(value).to_string()
+                        } else {
+                            MASK.to_string()
+                        };
+                        format!("  {}: {}", SanitizedPath(path), shown)
+                    })
+                    .collect();
+                (
+                    format!("{} ({} keys)", Sanitized(&keys.secret), keys.leaves.len()),
+                    body,
+                    keys.selected,
+                    "↑↓ move  r reveal  c copy  p print and exit  Esc back  q quit",
+                )
+            }
+            None => (
+                format!(
+                    "> {}  ({}/{})",
+                    Sanitized(&self.query),
+                    self.matches.len(),
+                    self.names.len()
+                ),
+                self.visible()
+                    .into_iter()
+                    .map(|name| format!("  {}", Sanitized(name)))
+                    .collect(),
+                self.selected,
+                "type to filter  ↑↓ move  Enter open  Esc quit",
+            ),
+        };
+
+        // Scroll just enough to keep the selection on screen.
+        let offset = (selected + 1).saturating_sub(rows);
+        let shown = body.len().saturating_sub(offset).min(rows);
+        let highlight =
+            (selected >= offset && selected - offset < shown).then(|| selected - offset + 1);
+
+        let mut lines = vec![header];
+        lines.extend(body.into_iter().skip(offset).take(rows));
+        lines.resize(height.saturating_sub(1).max(1), String::new());
+        let status = self.status.as_deref().map(|s| Sanitized(s).to_string());
+        lines.push(status.unwrap_or_else(|| help.to_string()));
+        lines.truncate(height);
+        for line in &mut lines {
+            if let Some((cut, _)) = line.char_indices().nth(width) {
+                line.truncate(cut);
+            }
+        }
+        Screen { lines, highlight }
+    }
+}
+
+/// Restores the terminal when dropped, including on error or panic.
+struct TerminalGuard;
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let guard = TerminalGuard;
+        execute!(io::stderr(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(guard)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw(screen: &Screen) -> io::Result<()> {
+    let mut out = io::stderr().lock();
+    for (y, line) in screen.lines.iter().enumerate() {
+        queue!(
+            out,
+            cursor::MoveTo(0, y as u16),
+            Clear(ClearType::CurrentLine)
+        )?;
+        if screen.highlight == Some(y) {
+            queue!(
+                out,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(out, Print(line))?;
+        }
+    }
+    out.flush()
+}
+
+/// Run the picker over `names`, fetching secrets with `fetch`. Returns the
+/// value chosen with `p`, if any.
+pub async fn run<F, Fut>(names: Vec<String>, query: &str, mut fetch: F) -> Result<Option<String>>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<BTreeMap<String, Value>>>,
+{
+    let mut browser = Browser::new(names, query);
+    let _guard = TerminalGuard::enter()?;
+    loop {
+        // Some pseudo-terminals report no size at all.
+        let (width, height) = terminal::size()
+            .ok()
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .unwrap_or((80, 24));
+        draw(&browser.render(width as usize, height as usize))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match browser.handle_key(key) {
+            Action::None => {}
+            Action::Quit => return Ok(None),
+            Action::Print(value) => return Ok(Some(value)),
+            Action::Open(secret) => match fetch(secret.clone()).await {
+                Ok(data) => browser.open(secret, &data),
+                Err(err) => browser.set_status(format!("error: {err:#}")),
+            },
+            Action::Copy(value) => {
+                match clipboard::copy_and_clear(&value, clipboard::DEFAULT_CLEAR_AFTER) {
+                    Ok((method, true)) => browser.set_status(format!(
+                        "copied ({}), clearing in {}s",
+                        method.describe(),
+                        clipboard::DEFAULT_CLEAR_AFTER.as_secs()
+                    )),
+                    Ok((method, false)) => {
+                        browser.set_status(format!("copied ({}), not cleared", method.describe()))
+                    }
+                    Err(err) => browser.set_status(format!("error: {err:#}")),
+                }
+            }
+        }
+    }
+}
+
+/// Print the value chosen in the picker in `format`.
+pub fn print_value(value: &str, format: OutputFormat) -> Result<()> {
+    let mut out = io::stdout().lock();
+    match format {
+        OutputFormat::Json => writeln!(out, "{}", serde_json::to_string(value)?)?,
+        OutputFormat::Plain => writeln!(out, "{}", Sanitized(value))?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn type_str(browser: &mut Browser, text: &str) {
+        for c in text.chars() {
+            browser.handle_key(key(KeyCode::Char(c)));
+        }
+    }
+
+    fn names() -> Vec<String> {
+        [
+            "dev/app/db",
+            "payment/stripe-api-key",
+            "prod/app/db",
+            "prod/web",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn test_live_filter_ranks_and_resets_selection() {
+        let mut browser = Browser::new(names(), "");
+        assert_eq!(browser.visible().len(), 4);
+        browser.handle_key(key(KeyCode::Down));
+        type_str(&mut browser, "prod/db");
+        assert_eq!(browser.visible()[0], "prod/app/db");
+        assert_eq!(browser.selected, 0);
+        browser.handle_key(key(KeyCode::Backspace));
+        assert_eq!(browser.query, "prod/d");
+        browser.handle_key(key(KeyCode::Esc));
+        assert_eq!(browser.visible().len(), 4);
+        assert_eq!(browser.handle_key(key(KeyCode::Esc)), Action::Quit);
+    }
+
+    #[test]
+    fn test_open_reveal_copy_and_print() {
+        let mut browser = Browser::new(names(), "stripe");
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Enter)),
+            Action::Open("payment/stripe-api-key".to_string())
+        );
+        let data =
+            serde_json::from_value(json!({"key": "sk_live", "meta": {"env": "prod"}})).unwrap();
+        browser.open("payment/stripe-api-key".to_string(), &data);
+
+        let frame = browser.render(80, 10).lines.join("\n");
+        assert!(frame.contains(&format!("key: {MASK}")));
+        assert!(!frame.contains("sk_live"));
+
+        browser.handle_key(key(KeyCode::Char('r')));
+        assert!(browser
+            .render(80, 10)
+            .lines
+            .join("\n")
+            .contains("key: sk_live"));
+        browser.handle_key(key(KeyCode::Char('r')));
+        assert!(!browser.render(80, 10).lines.join("\n").contains("sk_live"));
+
+        browser.handle_key(key(KeyCode::Down));
+        let frame = browser.render(80, 10);
+        assert_eq!(frame.highlight, Some(2));
+        assert!(frame.lines[2].contains("meta.env"));
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('c'))),
+            Action::Copy("prod".to_string())
+        );
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('p'))),
+            Action::Print("prod".to_string())
+        );
+        browser.handle_key(key(KeyCode::Esc));
+        assert_eq!(browser.visible(), ["payment/stripe-api-key"]);
+    }
+
+    #[test]
+    fn test_frame_fits_and_follows_selection() {
+        let many: Vec<String> = (0..50).map(|i| format!("secret-{i:02}")).collect();
+        let mut browser = Browser::new(many, "");
+        for _ in 0..30 {
+            browser.handle_key(key(KeyCode::Down));
+        }
+        let frame = browser.render(12, 10);
+        assert_eq!(frame.lines.len(), 10);
+        assert!(frame.lines.iter().all(|line| line.chars().count() <= 12));
+        let highlighted = &frame.lines[frame.highlight.unwrap()];
+        assert_eq!(highlighted, "  secret-30");
+    }
+
+    #[test]
+    fn test_hostile_text_is_escaped_in_every_frame() {
+        let hostile = "evil\x1b]0;pwned\x07\x1b[2J\u{202e}name".to_string();
+        let mut browser = Browser::new(vec![hostile.clone()], "");
+        type_str(&mut browser, "\x1b");
+        let data = serde_json::from_value(json!({ hostile.clone(): hostile.clone() })).unwrap();
+        browser.open(hostile.clone(), &data);
+        browser.handle_key(key(KeyCode::Char('r')));
+        browser.set_status(hostile);
+
+        let frame = browser.render(200, 10).lines.join("\n");
+        assert!(frame.contains("pwned"));
+        assert!(!frame
+            .chars()
+            .any(|c| (c.is_control() && c != '\n') || c == '\u{202e}'));
+    }
+}
@@ -0,0 +1,251 @@
+//! `get --format github-actions|gitlab`: export a secret's keys into a CI
+//! pipeline.
+//!
+//! GitHub Actions: every value is masked with `::add-mask::` before anything
+//! else is written, then each key is appended to `$GITHUB_ENV` and
+//! `$GITHUB_OUTPUT` as a `KEY<<DELIMITER` heredoc. The delimiter is random and
+//! never occurs inside the value, so a value cannot end its heredoc early and
+//! smuggle in further variables.
+//!
+//! GitLab CI: `KEY=value` lines on stdout for an `artifacts:reports:dotenv`
+//! file. GitLab cannot mask values at runtime and its dotenv format has no
+//! multi-line values, so values with line breaks are refused.
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::hash_map::RandomState;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::OpenOptions;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
+
+use crate::commands::{value_to_string, Sanitized};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Platform {
+    GithubActions,
+    Gitlab,
+}
+
+/// Files GitHub Actions reads variables from, in the order they are written.
+const GITHUB_FILES: [&str; 2] = ["GITHUB_ENV", "GITHUB_OUTPUT"];
+
+/// Whether `name` can be a CI variable name: `[A-Za-z_][A-Za-z0-9_]*`.
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Refuse the whole export if any key is not a variable name, so a pipeline
+/// never runs with a credential silently missing.
+fn check_names(data: &BTreeMap<String, Value>) -> Result<()> {
+    let invalid: Vec<String> = data
+        .keys()
+        .filter(|key| !is_variable_name(key))
+        .map(|key| format!("'{}'", Sanitized(key)))
+        .collect();
+    if !invalid.is_empty() {
+        bail!(
+            "key(s) not valid as CI variable names (allowed: [A-Za-z_][A-Za-z0-9_]*): {}",
+            invalid.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Escape workflow command data the way the runner unescapes it.
+fn escape_command_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// One `::add-mask::` line per line of every value. The runner masks
+/// multi-line secrets line by line, so each line is registered on its own.
+pub fn github_masks(data: &BTreeMap<String, Value>) -> String {
+    let mut out = String::new();
+    for value in data.values() {
+        let value = value_to_string(value);
+        for line in value.split(['\r', '\n']).filter(|line| !line.is_empty()) {
+            out.push_str(&format!("::add-mask::{}\n", escape_command_data(line)));
+        }
+    }
+    out
+}
+
+/// A fresh unpredictable delimiter. `RandomState` is seeded from the OS and
+/// each instance gets new keys; unpredictability is defence in depth, since
+/// [`github_file_entries`] also checks the delimiter against the value.
+pub fn random_delimiter() -> String {
+    let mut token = String::from("ghadelimiter_");
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(std::process::id().into());
+        token.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    token
+}
+
+/// `KEY<<DELIMITER` heredoc entries for `$GITHUB_ENV` / `$GITHUB_OUTPUT`.
+/// A delimiter that occurs anywhere in the value is discarded and a new one
+/// drawn from `delimiter`.
+pub fn github_file_entries(
+    data: &BTreeMap<String, Value>,
+    mut delimiter: impl FnMut() -> String,
+) -> Result<String> {
+    check_names(data)?;
+    let mut out = String::new();
+    for (key, value) in data {
+        let value = value_to_string(value);
+        let delimiter = (0..16)
+            .map(|_| delimiter())
+            .find(|d| !d.is_empty() && !value.contains(d.as_str()))
+            .with_context(|| format!("could not pick a heredoc delimiter for key '{key}'"))?;
+        out.push_str(&format!("{key}<<{delimiter}\n{value}\n{delimiter}\n"));
+    }
+    Ok(out)
+}
+
+/// `KEY=value` lines for a GitLab dotenv report.
+pub fn gitlab_dotenv(data: &BTreeMap<String, Value>) -> Result<String> {
+    check_names(data)?;
+    let multi_line: Vec<&str> = data
+        .iter()
+        .filter(|(_, value)| value_to_string(value).contains(['\r', '\n']))
+        .map(|(key, _)| key.as_str())
+        .collect();
+    if !multi_line.is_empty() {
+        bail!(
+            "GitLab dotenv reports cannot hold multi-line values: {}",
+            multi_line.join(", ")
+        );
+    }
+    Ok(data
+        .iter()
+        .map(|(key, value)| format!("{key}={}\n", value_to_string(value)))
+        .collect())
+}
+
+/// Export `data` for `platform`: to stdout and, for GitHub Actions, the
+/// runner's variable files.
+pub fn export(platform: Platform, data: &BTreeMap<String, Value>) -> Result<()> {
+    match platform {
+        Platform::GithubActions => {
+            let entries = github_file_entries(data, random_delimiter)?;
+            let files: Vec<(&str, String)> = GITHUB_FILES
+                .iter()
+                .filter_map(|var| Some((*var, env::var(var).ok().filter(|p| !p.is_empty())?)))
+                .collect();
+            if files.is_empty() {
+                bail!(
+                    "Neither GITHUB_ENV nor GITHUB_OUTPUT is set; is this a GitHub Actions step?"
+                );
+            }
+
+            // Masks go out before any value is written anywhere.
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(github_masks(data).as_bytes())?;
+            stdout.flush()?;
+
+            for (var, path) in files {
+                OpenOptions::new()
+                    .append(true)
+                    .create(true)
+                    .open(&path)
+                    .and_then(|mut file| file.write_all(entries.as_bytes()))
+                    .with_context(|| format!("Failed to write ${var} ('{path}')"))?;
+            }
+            eprintln!("exported {} key(s)", data.len());
+        }
+        Platform::Gitlab => {
+            io::stdout()
+                .lock()
+                .write_all(gitlab_dotenv(data)?.as_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data(pairs: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_masks_every_line_with_command_escaping() {
+        let masks = github_masks(&data(&[
+            ("CERT", json!("line1\r\nline2\n")),
+            ("TOKEN", json!("100%::x")),
+        ]));
+        assert_eq!(
+            masks,
+            "::add-mask::line1\n::add-mask::line2\n::add-mask::100%25::x\n"
+        );
+    }
+
+    #[test]
+    fn test_heredoc_entries() {
+        let entries = github_file_entries(
+            &data(&[("CERT", json!("a\nb")), ("PORT", json!(5432))]),
+            || "EOF".to_string(),
+        )
+        .unwrap();
+        assert_eq!(entries, "CERT<<EOF\na\nb\nEOF\nPORT<<EOF\n5432\nEOF\n");
+    }
+
+    #[test]
+    fn test_value_cannot_forge_delimiter() {
+        let forged = json!("x\nEOF\nINJECTED<<EOF\npwned\nEOF");
+        let mut candidates = ["EOF", "EOF", "SAFE"].into_iter();
+        let entries = github_file_entries(&data(&[("KEY", forged)]), || {
+            candidates.next().unwrap_or_default().to_string()
+        })
+        .unwrap();
+        assert!(entries.starts_with("KEY<<SAFE\n"));
+        assert!(entries.ends_with("\nSAFE\n"));
+        assert_eq!(entries.matches("\nSAFE\n").count(), 1);
+
+        let delimiter = random_delimiter();
+        assert!(delimiter.starts_with("ghadelimiter_"));
+        assert_ne!(delimiter, random_delimiter());
+    }
+
+    #[test]
+    fn test_invalid_names_are_refused() {
+        let err = github_file_entries(
+            &data(&[
+                ("ok", json!("1")),
+                ("A\nB", json!("2")),
+                ("x<<y", json!("3")),
+                ("\u{2066}KEY", json!("4")),
+            ]),
+            random_delimiter,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("'A\\x0aB'") && err.contains("'x<<y'") && err.contains("'\\u{2066}KEY'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_gitlab_dotenv() {
+        assert_eq!(
+            gitlab_dotenv(&data(&[("A", json!("1")), ("B", json!("x y"))])).unwrap(),
+            "A=1\nB=x y\n"
+        );
+        assert!(gitlab_dotenv(&data(&[("A", json!("1\nC=2"))])).is_err());
+    }
+}
@@ -1,5 +1,10 @@
 use clap::{Parser, Subcommand, ValueEnum};
 use serde::Serialize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::ci::Platform;
+use crate::embedded::Embedded;
 
 #[derive(Parser)]
 #[command(name = "goldfinch")]
@@ -9,35 +14,390 @@ pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
 
-    /// Output format
+    /// Output format (github-actions and gitlab are only supported by `get`)
     #[arg(short, long, value_enum, global = true, default_value = "json")]
-    pub format: OutputFormat,
+    pub format: Format,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     /// List all secret names in your AWS account
-    List,
+    List {
+        /// Draw the names as a hierarchy split on `/` (same as `tree`)
+        #[arg(long)]
+        tree: bool,
+    },
+
+    /// Show secret names as a hierarchy split on `/`, with the number of secrets under each node
+    Tree {
+        /// Only show the hierarchy below this prefix (e.g. prod/app)
+        root: Option<String>,
+
+        /// Levels to show below the root; deeper secrets are still counted
+        #[arg(long)]
+        depth: Option<usize>,
+    },
+
+    /// Pick a secret interactively: filter names as you type, open a secret's keys, reveal, copy or print a value
+    Browse {
+        /// Initial filter
+        query: Option<String>,
+    },
+
+    /// Copy one value to the clipboard without printing it, and clear it again after a while
+    Copy {
+        /// The secret name
+        secret_name: String,
+
+        /// The key, or a key path such as `db.password`
+        key: String,
+
+        /// Clear the clipboard after this long, if it still holds the value, e.g. `45s`, `2m`; `0s` never clears.
+        /// A value copied over OSC 52 (SSH, or no local tool) is never cleared: the terminal's clipboard cannot be read back to check it
+        #[arg(long, value_parser = parse_duration, default_value = "45s")]
+        clear_after: Duration,
+    },
+
+    /// Clear a value copied by `copy` once `--after-ms` milliseconds have passed (started by `copy`)
+    #[command(hide = true)]
+    ClearClipboard {
+        #[arg(long)]
+        method: String,
+
+        #[arg(long)]
+        after_ms: u64,
+    },
 
     /// Get all key-value pairs from a specific secret by name
     Get {
         /// The secret name
         secret_name: String,
+
+        /// Parse JSON documents stored as string values (`=base64` also unwraps base64-encoded JSON)
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "json")]
+        decode_embedded: Option<Embedded>,
     },
 
     /// Search for secrets and keys matching a pattern (searches both secret names and key names)
     Search {
         /// Search pattern (substring match)
-        pattern: String,
+        #[arg(required_unless_present = "query", conflicts_with = "query")]
+        pattern: Option<String>,
+
+        /// Boolean query instead of a pattern, e.g. 'key:password AND tag:env=prod AND NOT name:legacy/*'
+        #[arg(short, long)]
+        query: Option<String>,
+
+        /// Parse JSON documents stored as string values (`=base64` also unwraps base64-encoded JSON)
+        #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "json")]
+        decode_embedded: Option<Embedded>,
+
+        /// Match names, descriptions and tags only, from ListSecrets (never reads secret values)
+        #[arg(long, conflicts_with_all = ["query", "decode_embedded"])]
+        names_only: bool,
+
+        /// Rank secret names and key paths by similarity to the pattern instead of requiring a substring
+        #[arg(long, conflicts_with = "query")]
+        fuzzy: bool,
+
+        /// Most results shown by `--fuzzy`
+        #[arg(long, requires = "fuzzy", default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Inspect a secret's values for certificates and private keys
+    Inspect {
+        /// The secret name
+        secret_name: String,
+    },
+
+    /// Build a database connection string from an RDS-style secret
+    Dsn {
+        /// The secret name
+        secret_name: String,
+
+        /// What to emit: a connection URL, libpq `PG*` variables, or a `.pgpass` line
+        #[arg(long, value_enum, default_value = "url")]
+        style: DsnStyle,
+    },
+
+    /// Print a Kubernetes Secret (or ExternalSecret) manifest for one or more secrets
+    K8s {
+        /// Secret names or globs (`*`, `?`); a key held by two secrets is an error
+        #[arg(required = true)]
+        secrets: Vec<String>,
+
+        /// `metadata.name` of the manifest
+        #[arg(long)]
+        name: String,
+
+        /// `metadata.namespace` of the manifest
+        #[arg(long)]
+        namespace: Option<String>,
+
+        /// Label as `key=value`; repeatable
+        #[arg(long = "label", value_name = "KEY=VALUE", value_parser = parse_label)]
+        labels: Vec<(String, String)>,
+
+        /// Emit an external-secrets.io ExternalSecret referencing the AWS secrets instead of embedding values
+        #[arg(long, requires = "secret_store")]
+        external_secret: bool,
+
+        /// SecretStore the ExternalSecret reads through
+        #[arg(long)]
+        secret_store: Option<String>,
+
+        /// Use a ClusterSecretStore rather than a namespaced SecretStore
+        #[arg(long)]
+        cluster_store: bool,
+
+        /// ExternalSecret `refreshInterval`
+        #[arg(long, default_value = "1h")]
+        refresh_interval: String,
+    },
+
+    /// Speak Terraform's `external` data source protocol: read `{"secret": ..., "keys": ...}` on stdin, print a flat string map
+    TfExternal,
+
+    /// Verify the secrets a `goldfinch.toml` manifest requires for one environment (reports every problem)
+    Check {
+        /// Environment section of the manifest to check
+        #[arg(long)]
+        env: String,
+
+        /// Manifest path
+        #[arg(long, default_value = "goldfinch.toml")]
+        manifest: PathBuf,
+    },
+
+    /// Compare `<env>/<service>/<name>` secrets across environments: missing secrets and differing keys
+    Drift {
+        /// Environments to compare, comma-separated (e.g. dev,staging,prod)
+        #[arg(long, value_delimiter = ',', required = true)]
+        envs: Vec<String>,
+
+        /// Also flag keys whose value is the same in several environments (compared by SHA-256)
+        #[arg(long)]
+        identical: bool,
+    },
+
+    /// Search files, directories or stdin for values of the selected secrets (values are never printed)
+    Scan {
+        /// Files or directories to scan; reads stdin when omitted or `-`
+        paths: Vec<PathBuf>,
+
+        /// Secret name or glob (`*`, `?`) whose values to look for; repeatable
+        #[arg(short, long = "secret", required = true)]
+        secrets: Vec<String>,
+    },
+
+    /// Copy stdin to stdout with the selected secrets' values replaced by `[REDACTED:secret/key]`
+    Redact {
+        /// Secret name or glob (`*`, `?`) whose values to redact; repeatable
+        #[arg(short, long = "secret", required = true)]
+        secrets: Vec<String>,
+
+        /// Redact with the secrets that could be read when others cannot (their values pass through)
+        #[arg(long)]
+        allow_partial: bool,
+    },
+
+    /// Serve secrets on localhost in the AWS Secrets Manager Agent's request/response shape
+    Serve {
+        /// Port to listen on (bound to 127.0.0.1 only)
+        #[arg(long, default_value_t = 2773)]
+        port: u16,
+
+        /// How long a fetched secret is served from memory, e.g. `300s`, `5m`; `0s` disables caching
+        #[arg(long, value_parser = parse_duration, default_value = "300s")]
+        ttl: Duration,
+
+        /// File holding the token clients must send in `X-Aws-Parameters-Secrets-Token`
+        /// (default: the `AWS_TOKEN` environment variable, or `file://<path>` in it)
+        #[arg(long)]
+        token_file: Option<PathBuf>,
+    },
+
+    /// Write one read-only file per key into a directory (for systemd `LoadCredential=` or Docker-style secrets)
+    #[cfg(unix)]
+    Materialize {
+        /// The secret name
+        secret_name: String,
+
+        /// Directory to write into (created with mode 0700 if missing)
+        #[arg(long)]
+        dir: PathBuf,
+    },
+
+    /// Poll secrets for changes, re-render outputs and run a hook (one event per change on stdout)
+    #[cfg(unix)]
+    Watch {
+        /// Secret names or globs (`*`, `?`); a key held by two secrets is an error
+        #[arg(required = true)]
+        secrets: Vec<String>,
+
+        /// How often to check for changes, e.g. `60s`, `5m`
+        #[arg(long, value_parser = parse_duration, default_value = "60s")]
+        interval: Duration,
+
+        /// Shell command to run after outputs are updated (changed secrets in `GOLDFINCH_CHANGED_SECRETS`)
+        #[arg(long)]
+        on_change: Option<String>,
+
+        /// Keep a `KEY="value"` env file up to date
+        #[arg(long)]
+        env_file: Option<PathBuf>,
+
+        /// Keep `DEST` rendered from `SRC`, replacing each `{{ key }}`; repeatable
+        #[arg(long, value_name = "SRC:DEST", value_parser = parse_template_arg)]
+        template: Vec<(PathBuf, PathBuf)>,
+
+        /// Keep one file per key in this directory
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+
+    /// Run an agent that holds decrypted secrets in locked memory for `get` and `search`
+    #[cfg(unix)]
+    Agent {
+        #[command(subcommand)]
+        action: AgentCommand,
+    },
+
+    /// Generate an account-wide report from secret metadata
+    Report {
+        #[command(subcommand)]
+        report: Report,
+    },
+}
+
+#[cfg(unix)]
+#[derive(Subcommand)]
+pub enum AgentCommand {
+    /// Serve `get` and `search` on a Unix socket until stopped (prints the `GOLDFINCH_AGENT_SOCK` assignment)
+    Start {
+        /// Socket path (default: `$XDG_RUNTIME_DIR/goldfinch/agent.sock`)
+        #[arg(long)]
+        socket: Option<PathBuf>,
+
+        /// How long a fetched secret is held before it is dropped and re-fetched, e.g. `30m`, `8h`
+        #[arg(long, value_parser = parse_duration, default_value = "1h")]
+        lifetime: Duration,
+    },
+
+    /// Tell a running agent to drop its secrets and exit
+    Stop {
+        /// Socket path (default: `$GOLDFINCH_AGENT_SOCK`, then the `start` default)
+        #[arg(long)]
+        socket: Option<PathBuf>,
     },
 }
 
+#[derive(Subcommand)]
+pub enum Report {
+    /// Check every secret's metadata against a TOML rule set (plain format renders a table)
+    Compliance {
+        /// Path to the TOML rule file
+        #[arg(short, long)]
+        rules: PathBuf,
+    },
+
+    /// List certificates in any secret that expire within a window (or already have)
+    Expiring {
+        /// Report window, e.g. `30d`, `12h`, `2w`
+        #[arg(long, value_parser = parse_duration, default_value = "30d")]
+        within: Duration,
+    },
+
+    /// List JWTs in any secret that expire within a window (or already have)
+    Tokens {
+        /// Report window, e.g. `7d`, `12h`, `2w`
+        #[arg(long, value_parser = parse_duration, default_value = "7d")]
+        within: Duration,
+    },
+}
+
+/// Parse a duration such as `90s`, `15m`, `12h`, `30d` or `2w`.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(|| format!("missing unit in '{s}' (expected s, m, h, d or w)"))?;
+    let (digits, unit) = s.split_at(split);
+    let n: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid number in '{s}'"))?;
+    let seconds = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3_600,
+        "d" => 86_400,
+        "w" => 604_800,
+        _ => return Err(format!("unknown unit '{unit}' (expected s, m, h, d or w)")),
+    };
+    n.checked_mul(seconds)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("duration '{s}' is too large"))
+}
+
+/// Parse a `key=value` label.
+pub fn parse_label(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected KEY=VALUE, got '{s}'"))
+}
+
+/// Parse a `SRC:DEST` template pair.
+pub fn parse_template_arg(s: &str) -> Result<(PathBuf, PathBuf), String> {
+    match s.split_once(':') {
+        Some((source, dest)) if !source.is_empty() && !dest.is_empty() => {
+            Ok((PathBuf::from(source), PathBuf::from(dest)))
+        }
+        _ => Err(format!("expected SRC:DEST, got '{s}'")),
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
 pub enum OutputFormat {
     Json,
     Plain,
 }
 
+/// `--format`: the output formats every command supports, plus the CI
+/// formats that export a secret's keys into a pipeline.
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Plain,
+    GithubActions,
+    Gitlab,
+}
+
+impl Format {
+    pub fn ci(self) -> Option<Platform> {
+        match self {
+            Format::GithubActions => Some(Platform::GithubActions),
+            Format::Gitlab => Some(Platform::Gitlab),
+            Format::Json | Format::Plain => None,
+        }
+    }
+
+    /// The format for commands without CI output. A CI format maps to plain;
+    /// callers reject it before it gets that far.
+    pub fn output(self) -> OutputFormat {
+        match self {
+            Format::Json => OutputFormat::Json,
+            Format::Plain | Format::GithubActions | Format::Gitlab => OutputFormat::Plain,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum DsnStyle {
+    Url,
+    PgEnv,
+    Pgpass,
+}
+
 /// One search result.
 ///
 /// `secret` and `key` are kept as separate members rather than joined into a
@@ -48,9 +408,38 @@ pub enum OutputFormat {
 pub struct KeyValue {
     /// The secret that owns this record.
     pub secret: String,
-    /// The matched key within the secret, or `None` when the secret's own name
-    /// was what matched.
+    /// The top-level key the match is in, or `None` when the secret's own
+    /// name was what matched.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub key: Option<String>,
+    /// The full path to the matched value, starting with `key`: object keys
+    /// as strings, array indices as numbers (`["servers", 2, "host"]`).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub path: Vec<PathSegment>,
     pub value: String,
 }
+
+/// One step into a nested value.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(untagged)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration("30d"), Ok(Duration::from_secs(2_592_000)));
+        assert_eq!(parse_duration("2w"), Ok(Duration::from_secs(1_209_600)));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("3y").is_err());
+        assert!(parse_duration("99999999999999999999d").is_err());
+    }
+}
@@ -0,0 +1,334 @@
+//! Putting a value on the system clipboard, and taking it off again.
+//!
+//! Locally, the value is piped to the platform's clipboard tool (`wl-copy`
+//! on Wayland, `xclip` or `xsel` on X11, `pbcopy` on macOS); it is written to
+//! the tool's stdin, never passed as an argument. Over SSH, or when no tool
+//! is available, the OSC 52 escape sequence asks the terminal emulator to set
+//! the clipboard instead, which also works through a remote session. The
+//! sequence carries the value, so it is only written when stderr is a
+//! terminal; redirected into a log, it would be printed there.
+//!
+//! [`schedule_clear`] hands a SHA-256 digest of the value, not the value, to
+//! a detached `goldfinch clear-clipboard`, which empties the clipboard later
+//! only if it still holds that value. A terminal's clipboard cannot be read
+//! back over OSC 52, and by then the terminal may belong to another program,
+//! so a value copied that way is never cleared.
+
+use anyhow::{bail, Context, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// A local clipboard tool: the commands that set and read the clipboard.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tool {
+    pub name: &'static str,
+    copy: &'static [&'static str],
+    paste: &'static [&'static str],
+}
+
+const PBCOPY: Tool = Tool {
+    name: "pbcopy",
+    copy: &["pbcopy"],
+    paste: &["pbpaste"],
+};
+
+const WL_COPY: Tool = Tool {
+    name: "wl-copy",
+    copy: &["wl-copy"],
+    paste: &["wl-paste", "--no-newline"],
+};
+
+const XCLIP: Tool = Tool {
+    name: "xclip",
+    copy: &["xclip", "-selection", "clipboard"],
+    paste: &["xclip", "-selection", "clipboard", "-o"],
+};
+
+const XSEL: Tool = Tool {
+    name: "xsel",
+    copy: &["xsel", "--clipboard", "--input"],
+    paste: &["xsel", "--clipboard", "--output"],
+};
+
+const TOOLS: [&Tool; 4] = [&PBCOPY, &WL_COPY, &XCLIP, &XSEL];
+
+/// How long a copied value stays on the clipboard unless told otherwise.
+pub const DEFAULT_CLEAR_AFTER: Duration = Duration::from_secs(45);
+
+/// How a value reached the clipboard.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Tool(&'static Tool),
+    /// An OSC 52 escape sequence written to the terminal.
+    Osc52,
+}
+
+impl Method {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Method::Tool(tool) => tool.name,
+            Method::Osc52 => "osc52",
+        }
+    }
+
+    /// The method [`Method::describe`] names.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if name == "osc52" {
+            return Some(Method::Osc52);
+        }
+        TOOLS
+            .into_iter()
+            .find(|tool| tool.name == name)
+            .map(Method::Tool)
+    }
+}
+
+/// The local tools worth trying, given the session's environment.
+fn local_tools(wayland: bool, x11: bool) -> Vec<&'static Tool> {
+    let mut tools = Vec::new();
+    if cfg!(target_os = "macos") {
+        tools.push(&PBCOPY);
+    }
+    if wayland {
+        tools.push(&WL_COPY);
+    }
+    if x11 {
+        tools.push(&XCLIP);
+        tools.push(&XSEL);
+    }
+    tools
+}
+
+/// Whether this looks like a remote session, where a local tool would set
+/// the clipboard of the wrong machine.
+pub fn is_remote() -> bool {
+    ["SSH_TTY", "SSH_CONNECTION", "SSH_CLIENT"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|v| !v.is_empty()))
+}
+
+/// Copy `text` to the clipboard.
+pub fn copy(text: &str) -> Result<Method> {
+    let tools = if is_remote() {
+        Vec::new()
+    } else {
+        let set = |var: &str| std::env::var_os(var).is_some_and(|v| !v.is_empty());
+        local_tools(set("WAYLAND_DISPLAY"), set("DISPLAY"))
+    };
+    copy_via(&tools, std::io::stderr().is_terminal(), text)
+}
+
+/// Try each of `tools`, then OSC 52 if stderr is a `terminal`.
+fn copy_via(tools: &[&'static Tool], terminal: bool, text: &str) -> Result<Method> {
+    for tool in tools {
+        if pipe_to(tool.copy, text).is_ok() {
+            return Ok(Method::Tool(tool));
+        }
+    }
+    if !terminal {
+        bail!("no clipboard available: no clipboard tool worked, and stderr is not a terminal to send OSC 52 to");
+    }
+    copy_with(Method::Osc52, text)?;
+    Ok(Method::Osc52)
+}
+
+/// Copy `text` to the clipboard with `method`. OSC 52 is written to stderr
+/// as is; [`copy`] checks that it is a terminal first.
+pub fn copy_with(method: Method, text: &str) -> Result<()> {
+    match method {
+        Method::Tool(tool) => pipe_to(tool.copy, text),
+        Method::Osc52 => {
+            let mut tty = std::io::stderr();
+            tty.write_all(osc52(text).as_bytes())?;
+            tty.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// The clipboard's current text, or `None` where it cannot be read back
+/// (OSC 52).
+pub fn paste(method: Method) -> Result<Option<String>> {
+    let Method::Tool(tool) = method else {
+        return Ok(None);
+    };
+    let (program, args) = tool.paste.split_first().expect("paste command");
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to run '{program}'"))?;
+    if !output.status.success() {
+        bail!("'{program}' exited with {}", output.status);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+/// Run the command `argv` with `text` on its stdin.
+fn pipe_to(argv: &[&str], text: &str) -> Result<()> {
+    let (program, args) = argv.split_first().expect("copy command");
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run '{program}'"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if !status.success() {
+        bail!("'{program}' exited with {status}");
+    }
+    Ok(())
+}
+
+/// The OSC 52 sequence setting the clipboard to `text`. Base64 keeps the
+/// payload free of control characters, so it cannot end the sequence early.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+/// Hex SHA-256 of `text`, which is all the clearing process learns about it.
+pub fn digest(text: &str) -> String {
+    Sha256::digest(text.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Whether the clipboard text `current` is still the value with `digest`.
+/// Tools may add or drop a trailing newline, so that is ignored.
+pub fn still_holds(current: &str, digest_hex: &str) -> bool {
+    [current, current.trim_end_matches('\n')]
+        .iter()
+        .any(|text| digest(text) == digest_hex)
+}
+
+/// Empty the clipboard if it still holds the value with `digest_hex`.
+/// Returns whether it was cleared; a clipboard that cannot be read back is
+/// left alone.
+pub fn clear_if_unchanged(method: Method, digest_hex: &str) -> Result<bool> {
+    match paste(method)? {
+        Some(current) if still_holds(&current, digest_hex) => {
+            copy_with(method, "")?;
+            Ok(true)
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Copy `text`, then clear it after `clear_after` unless that is zero.
+/// Returns the method used and whether a clear was scheduled.
+pub fn copy_and_clear(text: &str, clear_after: Duration) -> Result<(Method, bool)> {
+    let method = copy(text)?;
+    let scheduled = !clear_after.is_zero() && schedule_clear(method, text, clear_after)?;
+    Ok((method, scheduled))
+}
+
+/// Start a detached `goldfinch clear-clipboard` that clears `value` from the
+/// clipboard after `after`. The process gets the value's digest on stdin and
+/// runs in its own process group, so it outlives this one and a Ctrl-C in
+/// the shell.
+///
+/// Returns `false`, starting nothing, for OSC 52: that clipboard cannot be
+/// checked before clearing, and the clear would be written to whatever
+/// program owns the terminal by then.
+pub fn schedule_clear(method: Method, value: &str, after: Duration) -> Result<bool> {
+    if method == Method::Osc52 {
+        return Ok(false);
+    }
+    let exe = std::env::current_exe().context("Failed to locate the goldfinch executable")?;
+    let mut command = Command::new(exe);
+    command
+        .args(["clear-clipboard", "--method", method.describe()])
+        .arg("--after-ms")
+        .arg(after.as_millis().to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut command, 0);
+    let mut child = command
+        .spawn()
+        .context("Failed to start the clipboard clearing process")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(digest(value).as_bytes())?;
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_osc52_payload_is_base64() {
+        let seq = osc52("p\x07w\x1b]0;title\x07");
+        assert!(seq.starts_with("\x1b]52;c;"));
+        assert!(seq.ends_with('\x07'));
+        let payload = &seq["\x1b]52;c;".len()..seq.len() - 1];
+        assert!(payload
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"+/=".contains(&b)));
+        assert_eq!(STANDARD.decode(payload).unwrap(), b"p\x07w\x1b]0;title\x07");
+    }
+
+    #[test]
+    fn test_local_tools_follow_the_session() {
+        let names = |wayland, x11| -> Vec<&str> {
+            local_tools(wayland, x11)
+                .into_iter()
+                .map(|tool| tool.name)
+                .filter(|name| *name != "pbcopy")
+                .collect()
+        };
+        assert_eq!(names(true, true), ["wl-copy", "xclip", "xsel"]);
+        assert_eq!(names(false, true), ["xclip", "xsel"]);
+        assert!(names(false, false).is_empty());
+    }
+
+    #[test]
+    fn test_no_osc52_without_a_terminal() {
+        const FAILING: Tool = Tool {
+            name: "false",
+            copy: &["false"],
+            paste: &["false"],
+        };
+        for tools in [&[][..], &[&FAILING][..]] {
+            let err = copy_via(tools, false, "s3cret").unwrap_err();
+            assert!(err.to_string().starts_with("no clipboard available"));
+        }
+    }
+
+    #[test]
+    fn test_method_names_round_trip() {
+        for method in TOOLS.into_iter().map(Method::Tool).chain([Method::Osc52]) {
+            assert_eq!(Method::from_name(method.describe()), Some(method));
+        }
+        assert_eq!(Method::from_name("sh"), None);
+    }
+
+    #[test]
+    fn test_still_holds_compares_digests() {
+        let hex = digest("s3cret");
+        assert_eq!(hex.len(), 64);
+        assert!(still_holds("s3cret", &hex));
+        assert!(still_holds("s3cret\n", &hex));
+        assert!(!still_holds("something else", &hex));
+        assert!(!still_holds("", &hex));
+    }
+
+    #[test]
+    fn test_osc52_is_never_cleared() {
+        let hex = digest("s3cret");
+        assert!(!clear_if_unchanged(Method::Osc52, &hex).unwrap());
+        assert!(!schedule_clear(Method::Osc52, "s3cret", Duration::from_millis(500)).unwrap());
+    }
+}
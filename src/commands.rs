@@ -4,7 +4,9 @@ use std::collections::BTreeMap;
 use std::fmt;
 use std::io::{self, Write};
 
-use crate::cli::{KeyValue, OutputFormat};
+use crate::aws::SecretMetadata;
+use crate::cli::{KeyValue, OutputFormat, PathSegment};
+use crate::error::Error;
 
 /// A string that renders safely into a plain-text record stream.
 ///
@@ -25,7 +27,14 @@ pub struct Sanitized<'a>(pub &'a str);
 /// rendered `secret/key` form injective (see VULN-003).
 pub struct SanitizedComponent<'a>(pub &'a str);
 
-fn escape_into(f: &mut fmt::Formatter<'_>, s: &str, escape_slash: bool) -> fmt::Result {
+/// A key path rendered as `db.password` or `servers[2].host`.
+///
+/// Keys are escaped like [`SanitizedComponent`] and additionally have `.`,
+/// `[` and `]` escaped, so a key containing them cannot pass for nesting and
+/// the rendered path maps back to exactly one path.
+pub struct SanitizedPath<'a>(pub &'a [PathSegment]);
+
+fn escape_into(f: &mut fmt::Formatter<'_>, s: &str, extra: &[char]) -> fmt::Result {
     for c in s.chars() {
         let u = c as u32;
         let must_escape = u < 0x20                        // C0 controls
@@ -35,7 +44,7 @@ fn escape_into(f: &mut fmt::Formatter<'_>, s: &str, escape_slash: bool) -> fmt::
             || (0x2066..=0x2069).contains(&u)             // bidi isolates
             || u == 0x2028                                // line separator
             || u == 0x2029                                // paragraph separator
-            || (escape_slash && c == '/')
+            || extra.contains(&c)
             || c == '\\'; // so the escape form itself stays unambiguous
 
         if !must_escape {
@@ -51,14 +60,102 @@ fn escape_into(f: &mut fmt::Formatter<'_>, s: &str, escape_slash: bool) -> fmt::
 
 impl fmt::Display for Sanitized<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        escape_into(f, self.0, false)
+        escape_into(f, self.0, &[])
     }
 }
 
 impl fmt::Display for SanitizedComponent<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        escape_into(f, self.0, true)
+        escape_into(f, self.0, &['/'])
+    }
+}
+
+impl fmt::Display for SanitizedPath<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                PathSegment::Key(key) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    escape_into(f, key, &['/', '.', '[', ']'])?;
+                }
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The unescaped `db.password` / `servers[2].host` form of `path`, which
+/// search patterns are matched against.
+pub fn key_path(path: &[PathSegment]) -> String {
+    let mut out = String::new();
+    for (i, segment) in path.iter().enumerate() {
+        match segment {
+            PathSegment::Key(key) => {
+                if i > 0 {
+                    out.push('.');
+                }
+                out.push_str(key);
+            }
+            PathSegment::Index(index) => out.push_str(&format!("[{index}]")),
+        }
+    }
+    out
+}
+
+/// Call `visit` with every leaf of `data` (scalars and empty objects or
+/// arrays) and its path, in key order.
+pub fn walk_leaves<'a>(
+    data: &'a BTreeMap<String, Value>,
+    visit: &mut dyn FnMut(&[PathSegment], &'a Value),
+) {
+    fn walk<'a>(
+        path: &mut Vec<PathSegment>,
+        value: &'a Value,
+        visit: &mut dyn FnMut(&[PathSegment], &'a Value),
+    ) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (key, child) in map {
+                    path.push(PathSegment::Key(key.clone()));
+                    walk(path, child, visit);
+                    path.pop();
+                }
+            }
+            Value::Array(items) if !items.is_empty() => {
+                for (index, child) in items.iter().enumerate() {
+                    path.push(PathSegment::Index(index));
+                    walk(path, child, visit);
+                    path.pop();
+                }
+            }
+            leaf => visit(path, leaf),
+        }
+    }
+
+    let mut path = Vec::new();
+    for (key, value) in data {
+        path.push(PathSegment::Key(key.clone()));
+        walk(&mut path, value, visit);
+        path.pop();
+    }
+}
+
+/// The value at `key`: a top-level key, or else the key path (see
+/// [`key_path`]) of a leaf.
+pub fn lookup(data: &BTreeMap<String, Value>, key: &str) -> Option<String> {
+    if let Some(value) = data.get(key) {
+        return Some(value_to_string(value));
     }
+    let mut found = None;
+    walk_leaves(data, &mut |path, value| {
+        if found.is_none() && key_path(path) == key {
+            found = Some(value_to_string(value));
+        }
+    });
+    found
 }
 
 /// The single plain-output record renderer. Every plain arm goes through it.
@@ -76,13 +173,13 @@ fn render_plain_line<W: Write>(w: &mut W, value: Sanitized) -> io::Result<()> {
 /// The `secret/key` identifier is composed here rather than pre-joined into the
 /// record, and each component escapes `/`, so the rendered identifier maps back
 /// to exactly one (secret, key) pair.
-fn render_search_record<W: Write>(w: &mut W, kv: &KeyValue) -> io::Result<()> {
+pub(crate) fn render_search_record<W: Write>(w: &mut W, kv: &KeyValue) -> io::Result<()> {
     match &kv.key {
-        Some(key) => writeln!(
+        Some(_) => writeln!(
             w,
             "{}/{}: {}",
             SanitizedComponent(&kv.secret),
-            SanitizedComponent(key),
+            SanitizedPath(&kv.path),
             Sanitized(&kv.value)
         ),
         None => writeln!(
@@ -94,6 +191,47 @@ fn render_search_record<W: Write>(w: &mut W, kv: &KeyValue) -> io::Result<()> {
     }
 }
 
+/// Plain renderer for tabular reports.
+///
+/// Every cell is escaped with [`Sanitized`] before its width is measured, so
+/// columns stay aligned and a cell cannot break out of its row.
+pub(crate) fn render_table<W: Write>(
+    w: &mut W,
+    headers: &[&str],
+    rows: &[Vec<String>],
+) -> io::Result<()> {
+    let rendered: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| Sanitized(cell).to_string()).collect())
+        .collect();
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rendered {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let write_row = |w: &mut W, cells: &[&str]| -> io::Result<()> {
+        let last = cells.len().saturating_sub(1);
+        for (i, (cell, width)) in cells.iter().zip(&widths).enumerate() {
+            if i == last {
+                write!(w, "{cell}")?;
+            } else {
+                write!(w, "{cell:<width$}  ")?;
+            }
+        }
+        writeln!(w)
+    };
+
+    write_row(w, headers)?;
+    for row in &rendered {
+        let cells: Vec<&str> = row.iter().map(String::as_str).collect();
+        write_row(w, &cells)?;
+    }
+    Ok(())
+}
+
 pub fn list_keys(secret_names: &[String], format: OutputFormat) -> Result<()> {
     write_keys(&mut io::stdout().lock(), secret_names, format)
 }
@@ -147,48 +285,116 @@ pub fn search_keys(
     write_search(&mut io::stdout().lock(), secrets_with_data, pattern, format)
 }
 
-pub fn write_search<W: Write>(
-    w: &mut W,
+/// Every search result for `pattern`: secrets whose name contains it, and
+/// every leaf value (see [`walk_leaves`]) whose key path contains it.
+pub fn search_matches(
     secrets_with_data: &BTreeMap<String, BTreeMap<String, Value>>,
     pattern: &str,
-    format: OutputFormat,
-) -> Result<()> {
+) -> Vec<KeyValue> {
     let mut matches: Vec<KeyValue> = Vec::new();
-
-    // Search through secret names and their keys
     for (secret_name, secret_data) in secrets_with_data {
-        // Check if secret name matches
         if secret_name.contains(pattern) {
             matches.push(KeyValue {
                 secret: secret_name.clone(),
                 key: None,
+                path: Vec::new(),
                 value: format!("{} keys", secret_data.len()),
             });
         }
 
-        // Check if any keys within the secret match
-        for (key, value) in secret_data {
-            if key.contains(pattern) {
+        walk_leaves(secret_data, &mut |path, value| {
+            // Paths always start with the top-level key.
+            let Some(PathSegment::Key(key)) = path.first() else {
+                return;
+            };
+            if key_path(path).contains(pattern) {
                 matches.push(KeyValue {
                     secret: secret_name.clone(),
                     key: Some(key.clone()),
+                    path: path.to_vec(),
                     value: value_to_string(value),
                 });
             }
-        }
+        });
+    }
+    matches
+}
+
+/// Whether a search for `pattern` reports anything from this secret.
+pub fn secret_matches(name: &str, data: &BTreeMap<String, Value>, pattern: &str) -> bool {
+    let mut found = name.contains(pattern);
+    if !found {
+        walk_leaves(data, &mut |path, _| {
+            found = found || key_path(path).contains(pattern);
+        });
     }
+    found
+}
+
+/// `search --names-only`: secrets whose name, description, or a tag key or
+/// value contains `pattern`, decided from ListSecrets output alone. Each
+/// record's value says where the pattern was found.
+pub fn metadata_matches(secrets: &[SecretMetadata], pattern: &str) -> Vec<KeyValue> {
+    secrets
+        .iter()
+        .filter_map(|secret| {
+            let mut found = Vec::new();
+            if secret.name.contains(pattern) {
+                found.push("name".to_string());
+            }
+            if let Some(description) = &secret.description {
+                if description.contains(pattern) {
+                    found.push(format!("description: {description}"));
+                }
+            }
+            for (key, value) in &secret.tags {
+                if key.contains(pattern) || value.contains(pattern) {
+                    found.push(format!("tag: {key}={value}"));
+                }
+            }
+            (!found.is_empty()).then(|| KeyValue {
+                secret: secret.name.clone(),
+                key: None,
+                path: Vec::new(),
+                value: found.join("; "),
+            })
+        })
+        .collect()
+}
 
+pub fn write_search<W: Write>(
+    w: &mut W,
+    secrets_with_data: &BTreeMap<String, BTreeMap<String, Value>>,
+    pattern: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let matches = search_matches(secrets_with_data, pattern);
     if matches.is_empty() {
-        bail!("No secrets or keys found matching pattern '{}'", pattern);
+        bail!(Error::NoMatches(format!(
+            "No secrets or keys found matching pattern '{}'",
+            pattern
+        )));
     }
+    write_matches(w, &matches, format)
+}
 
+pub fn show_matches(matches: &[KeyValue], format: OutputFormat) -> Result<()> {
+    write_matches(&mut io::stdout().lock(), matches, format)
+}
+
+/// Render search results, however they were found.
+pub fn write_matches<W: Write>(
+    w: &mut W,
+    matches: &[KeyValue],
+    format: OutputFormat,
+) -> Result<()> {
     match format {
         OutputFormat::Json => {
-            writeln!(w, "{}", serde_json::to_string_pretty(&matches)?)?;
+            writeln!(w, "{}", serde_json::to_string_pretty(matches)?)?;
         }
         OutputFormat::Plain => {
             for kv in matches {
-                render_search_record(w, &kv)?;
+                render_search_record(w, kv)?;
             }
         }
     }
@@ -453,6 +659,153 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn test_search_descends_into_nested_values() {
+        let mut secret = BTreeMap::new();
+        secret.insert(
+            "db".to_string(),
+            json!({"password": "hunter2", "replicas": []}),
+        );
+        secret.insert(
+            "servers".to_string(),
+            json!([{"host": "a"}, {"host": "b"}, {"host": "c", "port": 1}]),
+        );
+        let mut secrets = BTreeMap::new();
+        secrets.insert("app".to_string(), secret);
+
+        let paths: Vec<String> = search_matches(&secrets, "servers[2]")
+            .iter()
+            .map(|kv| key_path(&kv.path))
+            .collect();
+        assert_eq!(paths, ["servers[2].host", "servers[2].port"]);
+
+        let matches = search_matches(&secrets, "db.password");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].key.as_deref(), Some("db"));
+        assert_eq!(matches[0].value, "hunter2");
+        assert!(secret_matches("app", &secrets["app"], "replicas"));
+        assert!(!secret_matches("app", &secrets["app"], "missing"));
+
+        let mut out = Vec::new();
+        write_search(&mut out, &secrets, "host", OutputFormat::Plain).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "app/servers[0].host: a\napp/servers[1].host: b\napp/servers[2].host: c\n"
+        );
+
+        let mut out = Vec::new();
+        write_search(&mut out, &secrets, "db.password", OutputFormat::Json).unwrap();
+        let json: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["path"], json!(["db", "password"]));
+    }
+
+    #[test]
+    fn test_flat_dotted_keys_are_escaped_in_plain_output() {
+        let mut secret = BTreeMap::new();
+        secret.insert(
+            "spring.datasource.url".to_string(),
+            json!("jdbc:postgresql://db"),
+        );
+        secret.insert(
+            "spring".to_string(),
+            json!({"datasource": {"url": "nested"}}),
+        );
+        let mut secrets = BTreeMap::new();
+        secrets.insert("app".to_string(), secret);
+
+        // The flat key and the nested path would otherwise render alike.
+        let mut out = Vec::new();
+        write_search(&mut out, &secrets, "datasource", OutputFormat::Plain).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "app/spring.datasource.url: nested\n\
+             app/spring\\x2edatasource\\x2eurl: jdbc:postgresql://db\n"
+        );
+
+        // JSON output carries the key unchanged.
+        let mut out = Vec::new();
+        write_search(
+            &mut out,
+            &secrets,
+            "spring.datasource.url",
+            OutputFormat::Json,
+        )
+        .unwrap();
+        let json: Value = serde_json::from_slice(&out).unwrap();
+        let keys: Vec<&Value> = json
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|kv| &kv["key"])
+            .collect();
+        assert!(keys.contains(&&json!("spring.datasource.url")), "{json}");
+    }
+
+    #[test]
+    fn test_lookup_by_key_or_key_path() {
+        let mut secret = BTreeMap::new();
+        secret.insert(
+            "db".to_string(),
+            json!({"password": "hunter2", "port": 5432}),
+        );
+        secret.insert("db.password".to_string(), json!("literal"));
+        secret.insert("hosts".to_string(), json!(["a", "b"]));
+
+        assert_eq!(lookup(&secret, "db.password").as_deref(), Some("literal"));
+        assert_eq!(lookup(&secret, "db.port").as_deref(), Some("5432"));
+        assert_eq!(lookup(&secret, "hosts[1]").as_deref(), Some("b"));
+        assert_eq!(lookup(&secret, "hosts").as_deref(), Some(r#"["a","b"]"#));
+        assert_eq!(lookup(&secret, "missing"), None);
+    }
+
+    #[test]
+    fn test_metadata_matches_names_descriptions_and_tags() {
+        let secrets = vec![
+            SecretMetadata {
+                name: "prod/payments/db".to_string(),
+                description: Some("Payments primary\ndatabase".to_string()),
+                tags: BTreeMap::from([("team".to_string(), "payments".to_string())]),
+                ..Default::default()
+            },
+            SecretMetadata {
+                name: "prod/api".to_string(),
+                tags: BTreeMap::from([("payments-owner".to_string(), "x".to_string())]),
+                ..Default::default()
+            },
+            SecretMetadata {
+                name: "dev/api".to_string(),
+                description: Some("api for Payments".to_string()),
+                ..Default::default()
+            },
+        ];
+
+        let matches = metadata_matches(&secrets, "payments");
+        let found: Vec<(&str, &str)> = matches
+            .iter()
+            .map(|kv| (kv.secret.as_str(), kv.value.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("prod/payments/db", "name; tag: team=payments"),
+                ("prod/api", "tag: payments-owner=x"),
+            ]
+        );
+        assert!(metadata_matches(&secrets, "nothing").is_empty());
+
+        let mut out = Vec::new();
+        write_matches(
+            &mut out,
+            &metadata_matches(&secrets, "primary"),
+            OutputFormat::Plain,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[secret] prod\\x2fpayments\\x2fdb: description: Payments primary\\x0adatabase\n"
+        );
+    }
+
     #[test]
     fn test_unicode_in_values() {
         let mut secret = BTreeMap::new();
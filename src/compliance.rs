@@ -0,0 +1,497 @@
+//! `report compliance`: evaluate every secret's metadata against a rule set.
+//!
+//! Rules are loaded from a TOML file and checked against ListSecrets output,
+//! plus DescribeSecret when a rule needs replication status. No secret value is
+//! ever fetched, so the report runs under a metadata-only IAM policy.
+//!
+//! ```toml
+//! [[rule]]
+//! check = "rotation_enabled"
+//!
+//! [[rule]]
+//! id = "rotated-quarterly"
+//! check = "rotated_within"
+//! days = 90
+//!
+//! [[rule]]
+//! check = "customer_managed_key"
+//!
+//! [[rule]]
+//! check = "required_tags"
+//! tags = ["owner", "env"]
+//!
+//! [[rule]]
+//! check = "name_matches"
+//! pattern = "^(dev|staging|prod)/"
+//!
+//! [[rule]]
+//! check = "replicated_to"
+//! region = "us-west-2"
+//! ```
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::aws::SecretMetadata;
+use crate::cli::OutputFormat;
+use crate::commands::render_table;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// The KMS alias Secrets Manager uses when no customer key is configured.
+const AWS_MANAGED_KEY_ALIAS: &str = "alias/aws/secretsmanager";
+
+#[derive(Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<RawRule>,
+}
+
+#[derive(Deserialize)]
+struct RawRule {
+    id: Option<String>,
+    #[serde(flatten)]
+    check: RawCheck,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "check", rename_all = "snake_case")]
+enum RawCheck {
+    RotationEnabled,
+    RotatedWithin { days: u32 },
+    CustomerManagedKey,
+    RequiredTags { tags: Vec<String> },
+    NameMatches { pattern: String },
+    ReplicatedTo { region: String },
+}
+
+/// One check a secret's metadata must satisfy.
+#[derive(Debug)]
+pub enum Check {
+    RotationEnabled,
+    RotatedWithin { days: u32 },
+    CustomerManagedKey,
+    RequiredTags { tags: Vec<String> },
+    NameMatches { pattern: Regex },
+    ReplicatedTo { region: String },
+}
+
+#[derive(Debug)]
+pub struct Rule {
+    /// Identifier shown in the report; defaults to the check name.
+    pub id: String,
+    pub check: Check,
+}
+
+#[derive(Debug)]
+pub struct RuleSet {
+    pub rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read rule file '{}'", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid rule file '{}'", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: RuleFile = toml::from_str(text)?;
+        if file.rules.is_empty() {
+            bail!("rule file defines no [[rule]] entries");
+        }
+
+        let mut rules = Vec::with_capacity(file.rules.len());
+        for raw in file.rules {
+            let (default_id, check) = match raw.check {
+                RawCheck::RotationEnabled => ("rotation_enabled", Check::RotationEnabled),
+                RawCheck::RotatedWithin { days } => {
+                    ("rotated_within", Check::RotatedWithin { days })
+                }
+                RawCheck::CustomerManagedKey => ("customer_managed_key", Check::CustomerManagedKey),
+                RawCheck::RequiredTags { tags } => ("required_tags", Check::RequiredTags { tags }),
+                RawCheck::NameMatches { pattern } => {
+                    let pattern = Regex::new(&pattern)
+                        .with_context(|| format!("invalid name_matches pattern '{pattern}'"))?;
+                    ("name_matches", Check::NameMatches { pattern })
+                }
+                RawCheck::ReplicatedTo { region } => {
+                    ("replicated_to", Check::ReplicatedTo { region })
+                }
+            };
+            rules.push(Rule {
+                id: raw.id.unwrap_or_else(|| default_id.to_string()),
+                check,
+            });
+        }
+        Ok(RuleSet { rules })
+    }
+
+    /// Whether any rule needs DescribeSecret output rather than ListSecrets.
+    pub fn needs_replication_status(&self) -> bool {
+        self.rules
+            .iter()
+            .any(|rule| matches!(rule.check, Check::ReplicatedTo { .. }))
+    }
+}
+
+/// Whether a secret satisfied a rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// The metadata the rule needs could not be read, for example because
+    /// DescribeSecret failed.
+    Unknown,
+}
+
+impl From<bool> for Outcome {
+    fn from(passed: bool) -> Self {
+        if passed {
+            Outcome::Pass
+        } else {
+            Outcome::Fail
+        }
+    }
+}
+
+/// The outcome of one rule against one secret.
+#[derive(Debug, Serialize)]
+pub struct Finding {
+    pub secret: String,
+    pub rule: String,
+    pub result: Outcome,
+    pub detail: String,
+}
+
+/// Evaluate every rule against every secret. `now` is Unix seconds.
+pub fn evaluate(rules: &RuleSet, secrets: &[SecretMetadata], now: i64) -> Vec<Finding> {
+    let mut findings = Vec::with_capacity(rules.rules.len() * secrets.len());
+    for secret in secrets {
+        for rule in &rules.rules {
+            let (result, detail) = check(&rule.check, secret, now);
+            findings.push(Finding {
+                secret: secret.name.clone(),
+                rule: rule.id.clone(),
+                result,
+                detail,
+            });
+        }
+    }
+    findings
+}
+
+/// Whether `key` names the AWS managed key, as the bare alias or as an alias
+/// ARN (`arn:aws:kms:<region>:<account>:alias/aws/secretsmanager`).
+fn is_aws_managed_key(key: &str) -> bool {
+    key == AWS_MANAGED_KEY_ALIAS
+        || key
+            .strip_suffix(AWS_MANAGED_KEY_ALIAS)
+            .is_some_and(|arn| arn.starts_with("arn:") && arn.ends_with(':'))
+}
+
+fn check(check: &Check, secret: &SecretMetadata, now: i64) -> (Outcome, String) {
+    match check {
+        Check::RotationEnabled => {
+            if secret.rotation_enabled {
+                (Outcome::Pass, "rotation enabled".to_string())
+            } else {
+                (Outcome::Fail, "rotation disabled".to_string())
+            }
+        }
+        Check::RotatedWithin { days } => match secret.last_rotated {
+            Some(at) => {
+                let age = (now - at).max(0) / SECONDS_PER_DAY;
+                (
+                    (age <= i64::from(*days)).into(),
+                    format!("last rotated {age} days ago (limit {days})"),
+                )
+            }
+            None => (Outcome::Fail, "never rotated".to_string()),
+        },
+        Check::CustomerManagedKey => match secret.kms_key_id.as_deref() {
+            Some(key) if !is_aws_managed_key(key) => {
+                (Outcome::Pass, format!("encrypted with {key}"))
+            }
+            _ => (
+                Outcome::Fail,
+                "encrypted with aws/secretsmanager".to_string(),
+            ),
+        },
+        Check::RequiredTags { tags } => {
+            let missing: Vec<&str> = tags
+                .iter()
+                .filter(|tag| !secret.tags.contains_key(*tag))
+                .map(String::as_str)
+                .collect();
+            if missing.is_empty() {
+                (Outcome::Pass, "all required tags present".to_string())
+            } else {
+                (
+                    Outcome::Fail,
+                    format!("missing tags: {}", missing.join(", ")),
+                )
+            }
+        }
+        Check::NameMatches { pattern } => {
+            if pattern.is_match(&secret.name) {
+                (Outcome::Pass, format!("name matches {}", pattern.as_str()))
+            } else {
+                (
+                    Outcome::Fail,
+                    format!("name does not match {}", pattern.as_str()),
+                )
+            }
+        }
+        Check::ReplicatedTo { region } => match &secret.replica_regions {
+            None => (Outcome::Unknown, "replication status unknown".to_string()),
+            Some(regions) if regions.iter().any(|r| r == region) => {
+                (Outcome::Pass, format!("replicated to {region}"))
+            }
+            Some(_) => (Outcome::Fail, format!("not replicated to {region}")),
+        },
+    }
+}
+
+pub fn report_compliance(findings: &[Finding], format: OutputFormat) -> Result<()> {
+    write_compliance(&mut io::stdout().lock(), findings, format)
+}
+
+/// Render the findings, then fail if any rule did not pass so the report can
+/// gate a pipeline.
+pub fn write_compliance<W: Write>(
+    w: &mut W,
+    findings: &[Finding],
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(findings)?)?;
+        }
+        OutputFormat::Plain => {
+            let rows: Vec<Vec<String>> = findings
+                .iter()
+                .map(|f| {
+                    vec![
+                        f.secret.clone(),
+                        f.rule.clone(),
+                        match f.result {
+                            Outcome::Pass => "PASS",
+                            Outcome::Fail => "FAIL",
+                            Outcome::Unknown => "UNKNOWN",
+                        }
+                        .to_string(),
+                        f.detail.clone(),
+                    ]
+                })
+                .collect();
+            render_table(w, &["SECRET", "RULE", "RESULT", "DETAIL"], &rows)?;
+        }
+    }
+
+    let count = |result| findings.iter().filter(|f| f.result == result).count();
+    let (failed, unknown) = (count(Outcome::Fail), count(Outcome::Unknown));
+    if unknown > 0 {
+        bail!(
+            "{} of {} compliance checks failed and {} could not be evaluated",
+            failed,
+            findings.len(),
+            unknown
+        );
+    }
+    if failed > 0 {
+        bail!("{} of {} compliance checks failed", failed, findings.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_700_000_000;
+
+    fn secret(name: &str) -> SecretMetadata {
+        SecretMetadata {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn results(rules: &str, secret: &SecretMetadata) -> Vec<(String, bool)> {
+        let rules = RuleSet::parse(rules).expect("rules parse");
+        evaluate(&rules, std::slice::from_ref(secret), NOW)
+            .into_iter()
+            .map(|f| (f.rule, f.result == Outcome::Pass))
+            .collect()
+    }
+
+    #[test]
+    fn test_rule_ids_default_to_check_name() {
+        let rules = RuleSet::parse(
+            r#"
+            [[rule]]
+            check = "rotation_enabled"
+
+            [[rule]]
+            id = "quarterly"
+            check = "rotated_within"
+            days = 90
+            "#,
+        )
+        .unwrap();
+        let ids: Vec<&str> = rules.rules.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, ["rotation_enabled", "quarterly"]);
+    }
+
+    #[test]
+    fn test_unknown_check_is_rejected() {
+        let err = RuleSet::parse("[[rule]]\ncheck = \"sparkles\"\n").unwrap_err();
+        assert!(err.to_string().contains("sparkles"), "{err}");
+    }
+
+    #[test]
+    fn test_invalid_name_pattern_is_rejected() {
+        let err =
+            RuleSet::parse("[[rule]]\ncheck = \"name_matches\"\npattern = \"(\"\n").unwrap_err();
+        assert!(err.to_string().contains("name_matches"), "{err}");
+    }
+
+    #[test]
+    fn test_empty_rule_file_is_rejected() {
+        assert!(RuleSet::parse("").is_err());
+    }
+
+    #[test]
+    fn test_rotation_rules() {
+        let rules = r#"
+            [[rule]]
+            check = "rotation_enabled"
+            [[rule]]
+            check = "rotated_within"
+            days = 30
+        "#;
+
+        let mut s = secret("app");
+        assert_eq!(
+            results(rules, &s),
+            [
+                ("rotation_enabled".to_string(), false),
+                ("rotated_within".to_string(), false)
+            ]
+        );
+
+        s.rotation_enabled = true;
+        s.last_rotated = Some(NOW - 10 * SECONDS_PER_DAY);
+        assert!(results(rules, &s).iter().all(|(_, passed)| *passed));
+
+        s.last_rotated = Some(NOW - 31 * SECONDS_PER_DAY);
+        assert!(!results(rules, &s)[1].1);
+    }
+
+    #[test]
+    fn test_customer_managed_key() {
+        let rules = "[[rule]]\ncheck = \"customer_managed_key\"\n";
+        let mut s = secret("app");
+        assert!(!results(rules, &s)[0].1);
+
+        s.kms_key_id = Some(AWS_MANAGED_KEY_ALIAS.to_string());
+        assert!(!results(rules, &s)[0].1);
+
+        s.kms_key_id =
+            Some("arn:aws:kms:us-east-1:123456789012:alias/aws/secretsmanager".to_string());
+        assert!(!results(rules, &s)[0].1);
+
+        s.kms_key_id = Some("arn:aws:kms:us-east-1:123456789012:key/abc".to_string());
+        assert!(results(rules, &s)[0].1);
+
+        s.kms_key_id = Some("alias/team/alias/aws/secretsmanager".to_string());
+        assert!(results(rules, &s)[0].1);
+    }
+
+    #[test]
+    fn test_unknown_replication_is_not_a_failure() {
+        let rules = RuleSet::parse("[[rule]]\ncheck = \"replicated_to\"\nregion = \"us-west-2\"\n")
+            .unwrap();
+        let findings = evaluate(&rules, &[secret("prod/api")], NOW);
+        assert_eq!(findings[0].result, Outcome::Unknown);
+
+        let mut buf = Vec::new();
+        let err = write_compliance(&mut buf, &findings, OutputFormat::Plain).unwrap_err();
+        assert!(
+            err.to_string().contains("1 could not be evaluated"),
+            "{err}"
+        );
+        assert!(String::from_utf8(buf).unwrap().contains("UNKNOWN"));
+    }
+
+    #[test]
+    fn test_required_tags_reports_missing() {
+        let rules =
+            RuleSet::parse("[[rule]]\ncheck = \"required_tags\"\ntags = [\"owner\", \"env\"]\n")
+                .unwrap();
+        let mut s = secret("app");
+        s.tags.insert("env".to_string(), "prod".to_string());
+
+        let findings = evaluate(&rules, &[s], NOW);
+        assert_eq!(findings[0].result, Outcome::Fail);
+        assert_eq!(findings[0].detail, "missing tags: owner");
+    }
+
+    #[test]
+    fn test_name_and_replication_rules() {
+        let rules = r#"
+            [[rule]]
+            check = "name_matches"
+            pattern = "^(dev|prod)/"
+            [[rule]]
+            check = "replicated_to"
+            region = "us-west-2"
+        "#;
+        assert!(RuleSet::parse(rules).unwrap().needs_replication_status());
+
+        let mut s = secret("prod/api");
+        s.replica_regions = Some(Vec::new());
+        assert_eq!(
+            results(rules, &s),
+            [
+                ("name_matches".to_string(), true),
+                ("replicated_to".to_string(), false)
+            ]
+        );
+
+        s.replica_regions = Some(vec!["us-west-2".to_string()]);
+        assert!(results(rules, &s)[1].1);
+        assert!(!results(rules, &secret("legacy-api"))[0].1);
+    }
+
+    #[test]
+    fn test_report_fails_when_any_rule_fails() {
+        let findings = vec![
+            Finding {
+                secret: "a".to_string(),
+                rule: "r".to_string(),
+                result: Outcome::Pass,
+                detail: String::new(),
+            },
+            Finding {
+                secret: "b".to_string(),
+                rule: "r".to_string(),
+                result: Outcome::Fail,
+                detail: "bad\nrow".to_string(),
+            },
+        ];
+
+        let mut buf = Vec::new();
+        let err = write_compliance(&mut buf, &findings, OutputFormat::Plain).unwrap_err();
+        assert!(err.to_string().contains("1 of 2"), "{err}");
+
+        let out = String::from_utf8(buf).unwrap();
+        assert_eq!(out.lines().count(), 3, "header plus one line per finding");
+        assert!(out.contains("bad\\x0arow"));
+    }
+}
@@ -0,0 +1,314 @@
+//! `drift`: compare secrets across environments.
+//!
+//! Secrets are named `<env>/<rest>`; secrets with the same `<rest>` in
+//! different environments are aligned and compared. Reported are secrets
+//! missing from an environment, keys missing from some environments, and,
+//! with `--identical`, keys whose value is the same in several environments
+//! (usually a dev credential promoted to prod). Values are compared by
+//! SHA-256 digest and never printed.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::aws::FetchOutcome;
+use crate::cli::OutputFormat;
+use crate::commands::{render_table, value_to_string};
+
+/// Split `name` into its environment and the rest, if the environment is one
+/// of `envs`.
+pub fn split_env<'a>(name: &'a str, envs: &[String]) -> Option<(&'a str, &'a str)> {
+    let (env, rest) = name.split_once('/')?;
+    (!rest.is_empty() && envs.iter().any(|e| e == env)).then_some((env, rest))
+}
+
+/// The names in `names` that belong to one of `envs`.
+pub fn names_in_envs(names: &[String], envs: &[String]) -> Vec<String> {
+    names
+        .iter()
+        .filter(|name| split_env(name, envs).is_some())
+        .cloned()
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DriftKind {
+    /// The secret does not exist in these environments.
+    MissingSecret,
+    /// The secret could not be read in these environments.
+    Unreadable,
+    /// The key is absent from these environments' copies of the secret.
+    MissingKey,
+    /// These environments hold the same value for the key.
+    IdenticalValue,
+}
+
+impl DriftKind {
+    fn label(self) -> &'static str {
+        match self {
+            DriftKind::MissingSecret => "missing secret",
+            DriftKind::Unreadable => "unreadable",
+            DriftKind::MissingKey => "missing key",
+            DriftKind::IdenticalValue => "identical value",
+        }
+    }
+}
+
+/// One difference between environments.
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    /// The secret name without its environment prefix.
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub kind: DriftKind,
+    pub envs: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// One secret's copies by environment: its data, or why it could not be
+/// read.
+type Copies<'a> = BTreeMap<&'a str, Result<&'a BTreeMap<String, Value>, String>>;
+
+/// Compare every `<env>/<rest>` secret in `outcome` (successes and failures
+/// both count as existing) across `envs`.
+pub fn drift(envs: &[String], outcome: &FetchOutcome, identical: bool) -> Vec<Drift> {
+    let mut aligned: BTreeMap<&str, Copies> = BTreeMap::new();
+    for (name, data) in &outcome.secrets {
+        if let Some((env, rest)) = split_env(name, envs) {
+            aligned.entry(rest).or_default().insert(env, Ok(data));
+        }
+    }
+    for (name, err) in &outcome.failures {
+        if let Some((env, rest)) = split_env(name, envs) {
+            aligned
+                .entry(rest)
+                .or_default()
+                .insert(env, Err(err.to_string()));
+        }
+    }
+
+    let mut findings = Vec::new();
+    let finding = |name: &str, key: Option<&str>, kind, envs: Vec<&str>, detail| Drift {
+        name: name.to_string(),
+        key: key.map(str::to_string),
+        kind,
+        envs: envs.into_iter().map(str::to_string).collect(),
+        detail,
+    };
+
+    for (rest, by_env) in &aligned {
+        let missing: Vec<&str> = envs
+            .iter()
+            .map(String::as_str)
+            .filter(|env| !by_env.contains_key(env))
+            .collect();
+        if !missing.is_empty() {
+            findings.push(finding(rest, None, DriftKind::MissingSecret, missing, None));
+        }
+        for (env, copy) in by_env {
+            if let Err(err) = copy {
+                findings.push(finding(
+                    rest,
+                    None,
+                    DriftKind::Unreadable,
+                    vec![env],
+                    Some(err.clone()),
+                ));
+            }
+        }
+
+        // Keys are only compared between the copies that could be read, in
+        // the order the environments were given.
+        let readable: Vec<(&str, &BTreeMap<String, Value>)> = envs
+            .iter()
+            .filter_map(|env| Some((env.as_str(), *by_env.get(env.as_str())?.as_ref().ok()?)))
+            .collect();
+        let keys: BTreeSet<&String> = readable.iter().flat_map(|(_, data)| data.keys()).collect();
+        for key in keys {
+            let lacking: Vec<&str> = readable
+                .iter()
+                .filter(|(_, data)| !data.contains_key(key))
+                .map(|(env, _)| *env)
+                .collect();
+            if !lacking.is_empty() {
+                findings.push(finding(
+                    rest,
+                    Some(key),
+                    DriftKind::MissingKey,
+                    lacking,
+                    None,
+                ));
+            }
+            if identical {
+                let mut by_digest: BTreeMap<Vec<u8>, Vec<&str>> = BTreeMap::new();
+                for (env, data) in &readable {
+                    if let Some(value) = data.get(key) {
+                        let digest = Sha256::digest(value_to_string(value).as_bytes());
+                        by_digest.entry(digest.to_vec()).or_default().push(env);
+                    }
+                }
+                for sharing in by_digest.into_values().filter(|envs| envs.len() > 1) {
+                    findings.push(finding(
+                        rest,
+                        Some(key),
+                        DriftKind::IdenticalValue,
+                        sharing,
+                        None,
+                    ));
+                }
+            }
+        }
+    }
+    findings
+}
+
+pub fn report_drift(findings: &[Drift], format: OutputFormat) -> Result<()> {
+    write_drift(&mut io::stdout().lock(), findings, format)
+}
+
+/// Render the findings, then fail if there were any so `drift` can gate a
+/// pipeline.
+pub fn write_drift<W: Write>(w: &mut W, findings: &[Drift], format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(findings)?)?;
+        }
+        OutputFormat::Plain => {
+            let rows: Vec<Vec<String>> = findings
+                .iter()
+                .map(|d| {
+                    let mut drift = d.kind.label().to_string();
+                    if let Some(detail) = &d.detail {
+                        drift.push_str(&format!(" ({detail})"));
+                    }
+                    vec![
+                        d.name.clone(),
+                        d.key.clone().unwrap_or_else(|| "-".to_string()),
+                        drift,
+                        d.envs.join(","),
+                    ]
+                })
+                .collect();
+            render_table(w, &["NAME", "KEY", "DRIFT", "ENVS"], &rows)?;
+        }
+    }
+
+    if !findings.is_empty() {
+        bail!("{} drift finding(s) across environments", findings.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    fn envs() -> Vec<String> {
+        ["dev", "staging", "prod"].map(String::from).to_vec()
+    }
+
+    fn outcome(secrets: &[(&str, Value)], failures: &[&str]) -> FetchOutcome {
+        FetchOutcome {
+            secrets: secrets
+                .iter()
+                .map(|(name, data)| {
+                    let data: BTreeMap<String, Value> =
+                        serde_json::from_value(data.clone()).unwrap();
+                    (name.to_string(), data)
+                })
+                .collect(),
+            failures: failures
+                .iter()
+                .map(|name| (name.to_string(), anyhow!("AccessDeniedException")))
+                .collect(),
+        }
+    }
+
+    fn summary(findings: &[Drift]) -> Vec<String> {
+        findings
+            .iter()
+            .map(|d| {
+                format!(
+                    "{} {} {} {}",
+                    d.name,
+                    d.key.as_deref().unwrap_or("-"),
+                    d.kind.label(),
+                    d.envs.join(",")
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_env() {
+        let envs = envs();
+        assert_eq!(split_env("prod/app/db", &envs), Some(("prod", "app/db")));
+        assert_eq!(split_env("qa/app/db", &envs), None);
+        assert_eq!(split_env("prod/", &envs), None);
+        assert_eq!(split_env("prod", &envs), None);
+        assert_eq!(
+            names_in_envs(&["dev/a".to_string(), "other".to_string()], &envs),
+            ["dev/a"]
+        );
+    }
+
+    #[test]
+    fn test_missing_secrets_and_keys() {
+        let fetched = outcome(
+            &[
+                (
+                    "dev/app/db",
+                    json!({"user": "a", "password": "x", "debug": true}),
+                ),
+                ("staging/app/db", json!({"user": "a", "password": "y"})),
+                ("prod/app/db", json!({"user": "b", "password": "z"})),
+                ("dev/app/cache", json!({"url": "redis://dev"})),
+            ],
+            &["prod/app/api", "dev/app/api"],
+        );
+        assert_eq!(
+            summary(&drift(&envs(), &fetched, false)),
+            [
+                "app/api - missing secret staging",
+                "app/api - unreadable dev",
+                "app/api - unreadable prod",
+                "app/cache - missing secret staging,prod",
+                "app/db debug missing key staging,prod",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identical_values_by_digest() {
+        let fetched = outcome(
+            &[
+                ("dev/app/db", json!({"password": "same", "port": 5432})),
+                (
+                    "staging/app/db",
+                    json!({"password": "other", "port": "5432"}),
+                ),
+                ("prod/app/db", json!({"password": "same", "port": 5433})),
+            ],
+            &[],
+        );
+        let findings = drift(&envs(), &fetched, true);
+        assert_eq!(
+            summary(&findings),
+            [
+                "app/db password identical value dev,prod",
+                "app/db port identical value dev,staging",
+            ]
+        );
+        let mut out = Vec::new();
+        assert!(write_drift(&mut out, &findings, OutputFormat::Json).is_err());
+        assert!(!String::from_utf8(out).unwrap().contains("same"));
+    }
+}
@@ -0,0 +1,170 @@
+//! `--decode-embedded`: treat JSON documents stored as string values
+//! (`"config": "{\"a\":1}"`), optionally base64-wrapped, as native nesting.
+//!
+//! Only strings that parse to an object or array are replaced, so `"123"` or
+//! `"true"` stay strings. Decoding is bounded: a string is only considered
+//! up to [`MAX_STRING_LEN`] bytes, embedded documents nest at most
+//! [`MAX_DEPTH`] levels, and at most [`MAX_TOTAL_LEN`] bytes are decoded per
+//! secret. Anything beyond the limits is left as it was.
+
+use base64::engine::general_purpose::{STANDARD, URL_SAFE};
+use base64::Engine;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Longest string that is tried as an embedded document.
+pub const MAX_STRING_LEN: usize = 1 << 20;
+
+/// How many embedded documents may be nested inside one another.
+pub const MAX_DEPTH: usize = 8;
+
+/// Most bytes decoded for one secret, across all its strings.
+pub const MAX_TOTAL_LEN: usize = 8 << 20;
+
+/// Which encodings are recognized.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Embedded {
+    /// JSON text only.
+    Json,
+    /// JSON text, or base64 (standard or URL-safe) wrapping JSON text.
+    Base64,
+}
+
+/// Replace embedded documents in `data` in place.
+pub fn decode_embedded(data: &mut BTreeMap<String, Value>, mode: Embedded) {
+    let mut budget = MAX_TOTAL_LEN;
+    for value in data.values_mut() {
+        decode_value(value, mode, 0, &mut budget);
+    }
+}
+
+/// [`decode_embedded`] applied to every secret.
+pub fn decode_all(secrets: &mut BTreeMap<String, BTreeMap<String, Value>>, mode: Embedded) {
+    for data in secrets.values_mut() {
+        decode_embedded(data, mode);
+    }
+}
+
+fn decode_value(value: &mut Value, mode: Embedded, depth: usize, budget: &mut usize) {
+    match value {
+        Value::Object(map) => {
+            for child in map.values_mut() {
+                decode_value(child, mode, depth, budget);
+            }
+        }
+        Value::Array(items) => {
+            for child in items {
+                decode_value(child, mode, depth, budget);
+            }
+        }
+        Value::String(s) if depth < MAX_DEPTH => {
+            if let Some(mut decoded) = decode_string(s, mode, budget) {
+                decode_value(&mut decoded, mode, depth + 1, budget);
+                *value = decoded;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The object or array `s` encodes, if any, charging its length to `budget`.
+fn decode_string(s: &str, mode: Embedded, budget: &mut usize) -> Option<Value> {
+    if s.len() > MAX_STRING_LEN || s.len() > *budget {
+        return None;
+    }
+    if let Some(value) = parse_document(s.as_bytes()) {
+        *budget -= s.len();
+        return Some(value);
+    }
+    if mode == Embedded::Base64 {
+        let trimmed = s.trim();
+        let bytes = STANDARD
+            .decode(trimmed)
+            .or_else(|_| URL_SAFE.decode(trimmed))
+            .ok()?;
+        let value = parse_document(&bytes)?;
+        *budget -= s.len();
+        return Some(value);
+    }
+    None
+}
+
+/// Parse `bytes` as JSON if it looks like an object or array.
+fn parse_document(bytes: &[u8]) -> Option<Value> {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+    if !matches!(bytes[start], b'{' | b'[') {
+        return None;
+    }
+    serde_json::from_slice(bytes)
+        .ok()
+        .filter(|value: &Value| value.is_object() || value.is_array())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn decoded(data: Value, mode: Embedded) -> Value {
+        let mut data: BTreeMap<String, Value> = serde_json::from_value(data).unwrap();
+        decode_embedded(&mut data, mode);
+        serde_json::to_value(data).unwrap()
+    }
+
+    #[test]
+    fn test_embedded_json_becomes_nesting() {
+        let data = json!({
+            "config": "{\"db\": {\"url\": \"{\\\"host\\\": \\\"h\\\"}\"}}",
+            "list": [" [1, 2]"],
+            "number": "123",
+            "text": "{not json",
+        });
+        assert_eq!(
+            decoded(data, Embedded::Json),
+            json!({
+                "config": {"db": {"url": {"host": "h"}}},
+                "list": [[1, 2]],
+                "number": "123",
+                "text": "{not json",
+            })
+        );
+    }
+
+    #[test]
+    fn test_base64_only_in_base64_mode() {
+        let wrapped = STANDARD.encode(r#"{"password":"p"}"#);
+        let data = json!({ "blob": wrapped, "plain": "aGVsbG8=" });
+        assert_eq!(decoded(data.clone(), Embedded::Json), data);
+        assert_eq!(
+            decoded(data, Embedded::Base64),
+            json!({ "blob": {"password": "p"}, "plain": "aGVsbG8=" })
+        );
+    }
+
+    #[test]
+    fn test_depth_is_limited() {
+        let mut inner = json!({"leaf": true});
+        for _ in 0..MAX_DEPTH + 2 {
+            inner = json!({ "next": inner.to_string() });
+        }
+        let out = decoded(json!({ "deep": inner.to_string() }), Embedded::Json);
+        let mut node = &out["deep"];
+        let mut levels = 0;
+        while node.is_object() {
+            node = &node["next"];
+            levels += 1;
+        }
+        assert_eq!(levels, MAX_DEPTH);
+        assert!(node.is_string());
+    }
+
+    #[test]
+    fn test_oversized_strings_are_left_alone() {
+        let big = format!("[\"{}\"]", "x".repeat(MAX_STRING_LEN));
+        let out = decoded(json!({ "big": big }), Embedded::Json);
+        assert!(out["big"].is_string());
+    }
+}
@@ -0,0 +1,312 @@
+//! Typed errors for the failures scripts need to tell apart, and the exit
+//! code of each.
+//!
+//! Functions keep returning `anyhow::Result`. An [`Error`] is attached where a
+//! failure is classified, underneath any context added later, and
+//! [`error_kind`] finds it again. Exit codes are part of the CLI's interface:
+//! a code never changes meaning, and a new class gets a new code.
+//!
+//! The agent sends an error's class alongside its message
+//! ([`RelayedError`]), so a relayed error is classified like a local one.
+
+use aws_sdk_secretsmanager::error::{ProvideErrorMetadata, SdkError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::commands::Sanitized;
+
+/// A class of failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// `ResourceNotFoundException`: no secret by that name or ARN.
+    NotFound,
+    /// `AccessDeniedException`: the caller's IAM policy does not allow the
+    /// call.
+    AccessDenied,
+    /// The secret's KMS key would not decrypt it: `DecryptionFailure`, or a
+    /// key policy that does not grant `kms:Decrypt`.
+    KmsDecrypt,
+    /// The request was still throttled after the SDK's retries.
+    Throttled,
+    /// The secret value is not a JSON object of key-value pairs.
+    NotJson,
+    /// A search matched nothing.
+    NoMatches,
+}
+
+impl ErrorKind {
+    /// The process exit status for this class. Other errors exit with 1 and
+    /// invalid arguments with 2.
+    ///
+    /// | Code | Class |
+    /// |------|-------|
+    /// | 3 | [`ErrorKind::NotFound`] |
+    /// | 4 | [`ErrorKind::AccessDenied`] |
+    /// | 5 | [`ErrorKind::KmsDecrypt`] |
+    /// | 6 | [`ErrorKind::Throttled`] |
+    /// | 7 | [`ErrorKind::NotJson`] |
+    /// | 8 | [`ErrorKind::NoMatches`] |
+    pub const fn exit_code(self) -> u8 {
+        match self {
+            ErrorKind::NotFound => 3,
+            ErrorKind::AccessDenied => 4,
+            ErrorKind::KmsDecrypt => 5,
+            ErrorKind::Throttled => 6,
+            ErrorKind::NotJson => 7,
+            ErrorKind::NoMatches => 8,
+        }
+    }
+
+    /// Classify a Secrets Manager error code and its message.
+    fn from_service(code: &str, message: &str) -> Option<Self> {
+        match code {
+            "ResourceNotFoundException" => Some(ErrorKind::NotFound),
+            "DecryptionFailure" => Some(ErrorKind::KmsDecrypt),
+            // A key policy refusing the caller is reported as access denied,
+            // with "Access to KMS is not allowed".
+            "AccessDeniedException" if message.contains("KMS") => Some(ErrorKind::KmsDecrypt),
+            "AccessDeniedException" => Some(ErrorKind::AccessDenied),
+            "ThrottlingException" | "TooManyRequestsException" => Some(ErrorKind::Throttled),
+            _ => None,
+        }
+    }
+}
+
+/// A classified failure. Its message names what is missing, such as the IAM
+/// action or the KMS permission.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    NotFound {
+        secret: String,
+    },
+    /// `resource` is the secret, or `*` for account-wide actions.
+    AccessDenied {
+        action: &'static str,
+        resource: String,
+    },
+    KmsDecrypt {
+        secret: String,
+    },
+    Throttled {
+        action: &'static str,
+    },
+    NotJson,
+    NotObject,
+    /// The message says what was searched for.
+    NoMatches(String),
+    /// A classified failure relayed by the agent, with its message.
+    Relayed {
+        kind: ErrorKind,
+        message: String,
+    },
+}
+
+impl Error {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Error::NotFound { .. } => ErrorKind::NotFound,
+            Error::AccessDenied { .. } => ErrorKind::AccessDenied,
+            Error::KmsDecrypt { .. } => ErrorKind::KmsDecrypt,
+            Error::Throttled { .. } => ErrorKind::Throttled,
+            Error::NotJson | Error::NotObject => ErrorKind::NotJson,
+            Error::NoMatches(_) => ErrorKind::NoMatches,
+            Error::Relayed { kind, .. } => *kind,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound { secret } => write!(
+                f,
+                "Secrets Manager has no secret '{}' in this region",
+                Sanitized(secret)
+            ),
+            Error::AccessDenied { action, resource } if resource == "*" => write!(
+                f,
+                "Access denied: the caller's IAM policy does not allow {action}"
+            ),
+            Error::AccessDenied { action, resource } => write!(
+                f,
+                "Access denied: the caller's IAM policy does not allow {action} on secret '{}'",
+                Sanitized(resource)
+            ),
+            Error::KmsDecrypt { secret } => write!(
+                f,
+                "Secret '{}' could not be decrypted: the caller needs kms:Decrypt on the secret's KMS key, and the key must be enabled",
+                Sanitized(secret)
+            ),
+            Error::Throttled { action } => write!(
+                f,
+                "Throttled by Secrets Manager on {action}; retry later or raise AWS_MAX_ATTEMPTS"
+            ),
+            Error::NotJson => f.write_str("Secret value is not valid JSON"),
+            Error::NotObject => {
+                f.write_str("Secret value is not a JSON object with key-value pairs")
+            }
+            Error::NoMatches(message) | Error::Relayed { message, .. } => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Classify a failed call of the IAM `action` on `resource`. The SDK error
+/// stays in the chain, underneath the [`Error`] when there is one.
+pub fn from_sdk<E>(err: SdkError<E>, action: &'static str, resource: &str) -> anyhow::Error
+where
+    E: ProvideErrorMetadata + std::error::Error + Send + Sync + 'static,
+{
+    let kind = err
+        .code()
+        .and_then(|code| ErrorKind::from_service(code, err.message().unwrap_or_default()));
+    let typed = kind.map(|kind| match kind {
+        ErrorKind::NotFound => Error::NotFound {
+            secret: resource.to_string(),
+        },
+        ErrorKind::AccessDenied => Error::AccessDenied {
+            action,
+            resource: resource.to_string(),
+        },
+        ErrorKind::KmsDecrypt => Error::KmsDecrypt {
+            secret: resource.to_string(),
+        },
+        ErrorKind::Throttled => Error::Throttled { action },
+        ErrorKind::NotJson | ErrorKind::NoMatches => unreachable!("not a service error"),
+    });
+    let err = anyhow::Error::new(err);
+    match typed {
+        Some(typed) => err.context(typed),
+        None => err,
+    }
+}
+
+/// The class of `err`, from the [`Error`] in its chain.
+pub fn error_kind(err: &anyhow::Error) -> Option<ErrorKind> {
+    err.downcast_ref::<Error>().map(Error::kind)
+}
+
+/// An error as the agent sends it: the message with its whole context chain,
+/// and the class it had on the agent's side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayedError {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<ErrorKind>,
+    pub message: String,
+}
+
+impl RelayedError {
+    pub fn new(err: &anyhow::Error) -> Self {
+        RelayedError {
+            kind: error_kind(err),
+            message: format!("{err:#}"),
+        }
+    }
+
+    /// The error again, carrying an [`Error::Relayed`] when it was classified.
+    pub fn into_error(self) -> anyhow::Error {
+        match self.kind {
+            Some(kind) => anyhow::Error::new(Error::Relayed {
+                kind,
+                message: self.message,
+            }),
+            None => anyhow::anyhow!(self.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relayed_errors_keep_their_kind() {
+        let err = anyhow::Error::new(Error::KmsDecrypt {
+            secret: "prod/db".to_string(),
+        })
+        .context("Failed to fetch secret 'prod/db'");
+        let relayed = RelayedError::new(&err);
+        let json = serde_json::to_string(&relayed).unwrap();
+        assert!(json.contains(r#""kind":"kms_decrypt""#), "{json}");
+
+        let back: RelayedError = serde_json::from_str(&json).unwrap();
+        let err = back.into_error();
+        assert_eq!(error_kind(&err), Some(ErrorKind::KmsDecrypt));
+        assert!(err
+            .to_string()
+            .starts_with("Failed to fetch secret 'prod/db': "));
+
+        // Only the kind counts: text that quotes a service code or a fixed
+        // message does not classify an error.
+        for message in [
+            "Failed to read 'AccessDeniedException.txt'",
+            "Secret value is not valid JSON",
+        ] {
+            let relayed = RelayedError::new(&anyhow::anyhow!(message));
+            assert_eq!(relayed.kind, None);
+            assert_eq!(error_kind(&relayed.into_error()), None);
+        }
+    }
+
+    #[test]
+    fn test_error_kind_survives_context() {
+        let err = anyhow::Error::new(Error::Throttled {
+            action: "secretsmanager:ListSecrets",
+        })
+        .context("Failed to list secrets")
+        .context("while building suggestions");
+        assert_eq!(error_kind(&err), Some(ErrorKind::Throttled));
+        let err = anyhow::anyhow!(Error::NoMatches("No secrets found".to_string()));
+        assert_eq!(error_kind(&err), Some(ErrorKind::NoMatches));
+        assert_eq!(err.to_string(), "No secrets found");
+    }
+
+    #[test]
+    fn test_service_codes() {
+        let kind = |code| ErrorKind::from_service(code, "");
+        assert_eq!(kind("ResourceNotFoundException"), Some(ErrorKind::NotFound));
+        assert_eq!(kind("DecryptionFailure"), Some(ErrorKind::KmsDecrypt));
+        assert_eq!(kind("ThrottlingException"), Some(ErrorKind::Throttled));
+        assert_eq!(kind("InternalServiceError"), None);
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct_and_stable() {
+        let kinds = [
+            ErrorKind::NotFound,
+            ErrorKind::AccessDenied,
+            ErrorKind::KmsDecrypt,
+            ErrorKind::Throttled,
+            ErrorKind::NotJson,
+            ErrorKind::NoMatches,
+        ];
+        let codes: Vec<u8> = kinds.iter().map(|kind| kind.exit_code()).collect();
+        assert_eq!(codes, [3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_messages_name_the_missing_permission() {
+        let denied = Error::AccessDenied {
+            action: "secretsmanager:GetSecretValue",
+            resource: "prod/db\n".to_string(),
+        };
+        assert_eq!(
+            denied.to_string(),
+            "Access denied: the caller's IAM policy does not allow \
+             secretsmanager:GetSecretValue on secret 'prod/db\\x0a'"
+        );
+        let listing = Error::AccessDenied {
+            action: "secretsmanager:ListSecrets",
+            resource: "*".to_string(),
+        };
+        assert!(listing
+            .to_string()
+            .ends_with("does not allow secretsmanager:ListSecrets"));
+        let kms = Error::KmsDecrypt {
+            secret: "prod/db".to_string(),
+        };
+        assert!(kms.to_string().contains("kms:Decrypt"));
+    }
+}
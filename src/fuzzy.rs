@@ -0,0 +1,340 @@
+//! `search --fuzzy`: approximate matching of secret names and key paths,
+//! ranked by score.
+//!
+//! A candidate scores on whichever of two measures is higher:
+//!
+//! - **subsequence**: the pattern's characters appear in order in the
+//!   candidate (`pstrk` in `payments/stripe_key`). The tighter the shortest
+//!   window holding them, the higher the score; a plain substring scores 1.
+//! - **tokens**: both are split into words on anything that is not a letter
+//!   or digit, and each pattern word is paired with its closest candidate
+//!   word by edit distance. This finds `payment/stripe-api-key` for
+//!   `payments-api/stripe_key`, whatever the separators and word order.
+//!
+//! Matching ignores case. Scores run from 0 to 1; candidates below
+//! [`MIN_SCORE`] are dropped.
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::aws::SecretMetadata;
+use crate::cli::{KeyValue, OutputFormat, PathSegment};
+use crate::commands::{key_path, render_search_record, value_to_string, walk_leaves};
+use crate::error::Error;
+
+/// Lowest score reported.
+pub const MIN_SCORE: f64 = 0.6;
+
+/// Token matches are discounted so an in-order match outranks an equally
+/// close reordered one.
+const TOKEN_WEIGHT: f64 = 0.9;
+
+/// Candidates longer than this (in characters) are not scored, which keeps
+/// the quadratic matchers cheap on hostile key names.
+pub const MAX_CANDIDATE_LEN: usize = 1024;
+
+/// How well `pattern` matches `candidate`, from 0 (not at all) to 1.
+pub fn score(pattern: &str, candidate: &str) -> f64 {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let candidate: Vec<char> = candidate.to_lowercase().chars().collect();
+    if pattern.is_empty() || candidate.len() > MAX_CANDIDATE_LEN {
+        return 0.0;
+    }
+    subsequence_score(&pattern, &candidate).max(TOKEN_WEIGHT * token_score(&pattern, &candidate))
+}
+
+/// `0.5 + 0.5 * len / window` for the shortest window of `candidate` that
+/// holds `pattern` as a subsequence, or 0 when there is none.
+fn subsequence_score(pattern: &[char], candidate: &[char]) -> f64 {
+    // start[j]: latest start of a window ending here that holds pattern[..=j].
+    let mut start: Vec<Option<usize>> = vec![None; pattern.len()];
+    let mut best: Option<usize> = None;
+    for (i, &c) in candidate.iter().enumerate() {
+        for j in (0..pattern.len()).rev() {
+            if pattern[j] != c {
+                continue;
+            }
+            start[j] = if j == 0 { Some(i) } else { start[j - 1] };
+            if j + 1 == pattern.len() {
+                if let Some(s) = start[j] {
+                    let window = i - s + 1;
+                    best = Some(best.map_or(window, |b| b.min(window)));
+                }
+            }
+        }
+    }
+    best.map_or(0.0, |window| {
+        0.5 + 0.5 * pattern.len() as f64 / window as f64
+    })
+}
+
+/// Mean over the pattern's words of the similarity to the closest word of
+/// `candidate`.
+fn token_score(pattern: &[char], candidate: &[char]) -> f64 {
+    let words = |s: &[char]| -> Vec<Vec<char>> {
+        s.split(|c| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+            .map(<[char]>::to_vec)
+            .collect()
+    };
+    let wanted = words(pattern);
+    let available = words(candidate);
+    if wanted.is_empty() || available.is_empty() {
+        return 0.0;
+    }
+    let total: f64 = wanted
+        .iter()
+        .map(|w| {
+            available
+                .iter()
+                .map(|a| similarity(w, a))
+                .fold(0.0, f64::max)
+        })
+        .sum();
+    total / wanted.len() as f64
+}
+
+/// `1 - distance / longer length`.
+fn similarity(a: &[char], b: &[char]) -> f64 {
+    let longer = a.len().max(b.len());
+    if longer == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / longer as f64
+}
+
+/// Levenshtein distance.
+pub fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// A search result with its score.
+#[derive(Serialize)]
+pub struct Ranked {
+    /// Rounded to three decimals.
+    pub score: f64,
+    #[serde(flatten)]
+    pub record: KeyValue,
+}
+
+fn ranked(score: f64, record: KeyValue) -> Ranked {
+    Ranked {
+        score: (score * 1000.0).round() / 1000.0,
+        record,
+    }
+}
+
+/// Score every secret name and key path in `secrets` against `pattern`.
+pub fn rank_secrets(
+    secrets: &BTreeMap<String, BTreeMap<String, Value>>,
+    pattern: &str,
+    limit: usize,
+) -> Vec<Ranked> {
+    let mut results = Vec::new();
+    for (name, data) in secrets {
+        let s = score(pattern, name);
+        if s >= MIN_SCORE {
+            results.push(ranked(
+                s,
+                KeyValue {
+                    secret: name.clone(),
+                    key: None,
+                    path: Vec::new(),
+                    value: format!("{} keys", data.len()),
+                },
+            ));
+        }
+        walk_leaves(data, &mut |path, value| {
+            let Some(PathSegment::Key(key)) = path.first() else {
+                return;
+            };
+            let s = score(pattern, &key_path(path));
+            if s >= MIN_SCORE {
+                results.push(ranked(
+                    s,
+                    KeyValue {
+                        secret: name.clone(),
+                        key: Some(key.clone()),
+                        path: path.to_vec(),
+                        value: value_to_string(value),
+                    },
+                ));
+            }
+        });
+    }
+    sort_and_limit(results, limit)
+}
+
+/// Score secret names only, from ListSecrets output. The value is the
+/// secret's description.
+pub fn rank_names(secrets: &[SecretMetadata], pattern: &str, limit: usize) -> Vec<Ranked> {
+    let results = secrets
+        .iter()
+        .filter_map(|secret| {
+            let s = score(pattern, &secret.name);
+            (s >= MIN_SCORE).then(|| {
+                ranked(
+                    s,
+                    KeyValue {
+                        secret: secret.name.clone(),
+                        key: None,
+                        path: Vec::new(),
+                        value: secret.description.clone().unwrap_or_default(),
+                    },
+                )
+            })
+        })
+        .collect();
+    sort_and_limit(results, limit)
+}
+
+/// Best first; ties go to the shorter, then alphabetically earlier, result.
+fn sort_and_limit(mut results: Vec<Ranked>, limit: usize) -> Vec<Ranked> {
+    let label = |r: &Ranked| (r.record.secret.clone(), key_path(&r.record.path));
+    results.sort_by(|a, b| {
+        let (a_label, b_label) = (label(a), label(b));
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| {
+                (a_label.0.len() + a_label.1.len()).cmp(&(b_label.0.len() + b_label.1.len()))
+            })
+            .then_with(|| a_label.cmp(&b_label))
+    });
+    results.truncate(limit);
+    results
+}
+
+pub fn show_ranked(results: &[Ranked], pattern: &str, format: OutputFormat) -> Result<()> {
+    write_ranked(&mut io::stdout().lock(), results, pattern, format)
+}
+
+pub fn write_ranked<W: Write>(
+    w: &mut W,
+    results: &[Ranked],
+    pattern: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    if results.is_empty() {
+        bail!(Error::NoMatches(format!(
+            "No secrets or keys found resembling '{}'",
+            pattern
+        )));
+    }
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(results)?)?;
+        }
+        OutputFormat::Plain => {
+            for r in results {
+                write!(w, "{:.3}  ", r.score)?;
+                render_search_record(w, &r.record)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_edit_distance() {
+        let d = |a: &str, b: &str| {
+            let a: Vec<char> = a.chars().collect();
+            let b: Vec<char> = b.chars().collect();
+            edit_distance(&a, &b)
+        };
+        assert_eq!(d("kitten", "sitting"), 3);
+        assert_eq!(d("", "abc"), 3);
+        assert_eq!(d("stripe", "stripe"), 0);
+    }
+
+    #[test]
+    fn test_scores() {
+        assert_eq!(score("stripe", "prod/stripe"), 1.0);
+        assert_eq!(score("STRIPE", "prod/stripe"), 1.0);
+        assert!(score("pstrk", "payments/stripe_key") > MIN_SCORE);
+        // Different separators, word order and a missing plural.
+        let reordered = score("payments-api/stripe_key", "payment/stripe-api-key");
+        assert!(reordered > 0.8, "{reordered}");
+        assert!(score("stripe", "prod/stripe") > reordered);
+        assert!(score("payments", "database") < MIN_SCORE);
+        assert_eq!(score("", "anything"), 0.0);
+        assert_eq!(score("x", &"x".repeat(MAX_CANDIDATE_LEN + 1)), 0.0);
+    }
+
+    #[test]
+    fn test_ranking_and_limit() {
+        let mut secrets = BTreeMap::new();
+        secrets.insert(
+            "payment/stripe-api-key".to_string(),
+            serde_json::from_value(json!({"key": "sk_live"})).unwrap(),
+        );
+        secrets.insert(
+            "prod/db".to_string(),
+            serde_json::from_value(json!({"stripe_key": "sk_test", "password": "p"})).unwrap(),
+        );
+
+        let results = rank_secrets(&secrets, "stripe_key", 10);
+        let found: Vec<(&str, Option<&str>)> = results
+            .iter()
+            .map(|r| (r.record.secret.as_str(), r.record.key.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("prod/db", Some("stripe_key")),
+                ("payment/stripe-api-key", None)
+            ]
+        );
+        assert_eq!(rank_secrets(&secrets, "stripe_key", 1).len(), 1);
+
+        let mut out = Vec::new();
+        write_ranked(&mut out, &results, "stripe_key", OutputFormat::Json).unwrap();
+        let json: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json[0]["score"], json!(1.0));
+        assert_eq!(json[0]["secret"], "prod/db");
+        assert_eq!(json[0]["value"], "sk_test");
+
+        let mut out = Vec::new();
+        write_ranked(&mut out, &results[..1], "stripe_key", OutputFormat::Plain).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1.000  prod\\x2fdb/stripe_key: sk_test\n"
+        );
+        assert!(write_ranked(&mut Vec::new(), &[], "x", OutputFormat::Plain).is_err());
+    }
+
+    #[test]
+    fn test_rank_names_uses_metadata_only() {
+        let secrets = vec![
+            SecretMetadata {
+                name: "payments-api/stripe_key".to_string(),
+                description: Some("Stripe".to_string()),
+                ..Default::default()
+            },
+            SecretMetadata {
+                name: "prod/db".to_string(),
+                ..Default::default()
+            },
+        ];
+        let results = rank_names(&secrets, "payment/stripe-api-key", 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].record.value, "Stripe");
+    }
+}
@@ -0,0 +1,338 @@
+//! `k8s`: Kubernetes manifests from secrets.
+//!
+//! Either a `v1/Secret` embedding the values (base64 `data`), or an
+//! external-secrets.io `ExternalSecret` that references the AWS secrets and
+//! embeds nothing. Every scalar is emitted as a JSON string, which is also a
+//! valid YAML double-quoted scalar, so no name, label or value can change the
+//! document's structure.
+
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use crate::commands::{value_to_string, Sanitized};
+
+/// `metadata` shared by both manifest kinds.
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub name: String,
+    pub namespace: Option<String>,
+    pub labels: Vec<(String, String)>,
+}
+
+impl Metadata {
+    /// Check every field against the Kubernetes API's validation rules.
+    pub fn validate(&self) -> Result<()> {
+        check(&self.name, "name", is_dns_subdomain)?;
+        if let Some(namespace) = &self.namespace {
+            check(namespace, "namespace", is_dns_label)?;
+        }
+        for (key, value) in &self.labels {
+            check(key, "label key", is_qualified_name)?;
+            if !value.is_empty() {
+                check(value, "label value", is_label_name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str("metadata:\n");
+        let _ = writeln!(out, "  name: {}", quote(&self.name));
+        if let Some(namespace) = &self.namespace {
+            let _ = writeln!(out, "  namespace: {}", quote(namespace));
+        }
+        if !self.labels.is_empty() {
+            out.push_str("  labels:\n");
+            for (key, value) in &self.labels {
+                let _ = writeln!(out, "    {}: {}", quote(key), quote(value));
+            }
+        }
+    }
+}
+
+fn check(value: &str, what: &str, valid: fn(&str) -> bool) -> Result<()> {
+    if !valid(value) {
+        bail!("invalid Kubernetes {what} '{}'", Sanitized(value));
+    }
+    Ok(())
+}
+
+/// RFC 1123 subdomain: lowercase alphanumerics, `-` and `.`, starting and
+/// ending with an alphanumeric, at most 253 characters.
+pub fn is_dns_subdomain(s: &str) -> bool {
+    s.len() <= 253 && !s.is_empty() && s.split('.').all(is_dns_label)
+}
+
+/// RFC 1123 label: lowercase alphanumerics and `-`, starting and ending with
+/// an alphanumeric, at most 63 characters.
+pub fn is_dns_label(s: &str) -> bool {
+    let alnum = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit();
+    s.len() <= 63
+        && s.starts_with(alnum)
+        && s.ends_with(alnum)
+        && s.chars().all(|c| alnum(c) || c == '-')
+}
+
+/// Label name part: alphanumerics, `-`, `_` and `.`, starting and ending with
+/// an alphanumeric, at most 63 characters.
+fn is_label_name(s: &str) -> bool {
+    let alnum = |c: char| c.is_ascii_alphanumeric();
+    s.len() <= 63
+        && s.starts_with(alnum)
+        && s.ends_with(alnum)
+        && s.chars().all(|c| alnum(c) || matches!(c, '-' | '_' | '.'))
+}
+
+/// Label key: an optional DNS subdomain prefix and `/`, then a label name.
+fn is_qualified_name(s: &str) -> bool {
+    match s.split_once('/') {
+        Some((prefix, name)) => is_dns_subdomain(prefix) && is_label_name(name),
+        None => is_label_name(s),
+    }
+}
+
+/// A key in a Secret's `data`: alphanumerics, `-`, `_` and `.`, at most 253
+/// characters, and not `.` or `..`.
+pub fn is_data_key(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 253
+        && s != "."
+        && s != ".."
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Quote `s` as a YAML double-quoted scalar.
+fn quote(s: &str) -> String {
+    Value::String(s.to_string()).to_string()
+}
+
+/// Merge the keys of several secrets into one Secret's `data`.
+///
+/// A key held by more than one secret is an error, listing every clash with
+/// the secrets holding it: one value would otherwise replace the other
+/// without notice.
+pub fn merge_data(
+    secrets: Vec<(&str, BTreeMap<String, Value>)>,
+) -> Result<BTreeMap<String, Value>> {
+    let mut owners: BTreeMap<String, Vec<&str>> = BTreeMap::new();
+    let mut data = BTreeMap::new();
+    for (secret, values) in secrets {
+        for (key, value) in values {
+            owners.entry(key.clone()).or_default().push(secret);
+            data.insert(key, value);
+        }
+    }
+    let clashes: Vec<String> = owners
+        .iter()
+        .filter(|(_, secrets)| secrets.len() > 1)
+        .map(|(key, secrets)| {
+            let secrets: Vec<String> = secrets
+                .iter()
+                .map(|secret| format!("'{}'", Sanitized(secret)))
+                .collect();
+            format!("'{}' ({})", Sanitized(key), secrets.join(", "))
+        })
+        .collect();
+    if !clashes.is_empty() {
+        bail!(
+            "key(s) held by more than one secret: {}",
+            clashes.join(", ")
+        );
+    }
+    Ok(data)
+}
+
+/// A `v1/Secret` of type `Opaque` holding every key of `data`.
+///
+/// Every key that Kubernetes would reject is reported at once, rather than
+/// dropped, so the manifest never silently lacks a credential.
+pub fn render_secret(metadata: &Metadata, data: &BTreeMap<String, Value>) -> Result<String> {
+    metadata.validate()?;
+    let invalid: Vec<String> = data
+        .keys()
+        .filter(|key| !is_data_key(key))
+        .map(|key| format!("'{}'", Sanitized(key)))
+        .collect();
+    if !invalid.is_empty() {
+        bail!(
+            "key(s) not valid in a Kubernetes Secret (allowed: [-._a-zA-Z0-9]): {}",
+            invalid.join(", ")
+        );
+    }
+
+    let mut out = String::from("apiVersion: v1\nkind: Secret\n");
+    metadata.render(&mut out);
+    out.push_str("type: Opaque\n");
+    if data.is_empty() {
+        out.push_str("data: {}\n");
+    } else {
+        out.push_str("data:\n");
+        for (key, value) in data {
+            let encoded = STANDARD.encode(value_to_string(value));
+            let _ = writeln!(out, "  {}: {}", quote(key), quote(&encoded));
+        }
+    }
+    Ok(out)
+}
+
+/// Where an `ExternalSecret` reads from.
+#[derive(Debug, Clone)]
+pub struct StoreRef {
+    pub name: String,
+    /// `SecretStore` or `ClusterSecretStore`.
+    pub kind: &'static str,
+    pub refresh_interval: String,
+}
+
+/// An external-secrets.io `ExternalSecret` that extracts every key of each
+/// named AWS secret into a Kubernetes Secret called `metadata.name`. No value
+/// is fetched or embedded.
+pub fn render_external_secret(
+    metadata: &Metadata,
+    secret_names: &[String],
+    store: &StoreRef,
+) -> Result<String> {
+    metadata.validate()?;
+    check(&store.name, "secret store name", is_dns_subdomain)?;
+
+    let mut out = String::from("apiVersion: external-secrets.io/v1\nkind: ExternalSecret\n");
+    metadata.render(&mut out);
+    out.push_str("spec:\n");
+    let _ = writeln!(out, "  refreshInterval: {}", quote(&store.refresh_interval));
+    out.push_str("  secretStoreRef:\n");
+    let _ = writeln!(out, "    name: {}", quote(&store.name));
+    let _ = writeln!(out, "    kind: {}", quote(store.kind));
+    out.push_str("  target:\n");
+    let _ = writeln!(out, "    name: {}", quote(&metadata.name));
+    out.push_str("    creationPolicy: \"Owner\"\n");
+    out.push_str("  dataFrom:\n");
+    for name in secret_names {
+        out.push_str("    - extract:\n");
+        let _ = writeln!(out, "        key: {}", quote(name));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metadata() -> Metadata {
+        Metadata {
+            name: "app-secrets".to_string(),
+            namespace: Some("prod".to_string()),
+            labels: vec![("app.kubernetes.io/name".to_string(), "app".to_string())],
+        }
+    }
+
+    #[test]
+    fn test_name_rules() {
+        assert!(is_dns_subdomain("app.example-1"));
+        assert!(!is_dns_subdomain("App"));
+        assert!(!is_dns_subdomain("-app"));
+        assert!(!is_dns_label("a.b"));
+        assert!(is_qualified_name("example.com/tier"));
+        assert!(!is_qualified_name("Example.com/tier"));
+        assert!(!is_qualified_name("tier/"));
+        assert!(is_data_key("tls.crt"));
+        assert!(!is_data_key(".."));
+        assert!(!is_data_key("a/b"));
+    }
+
+    #[test]
+    fn test_secret_manifest() {
+        let data = [
+            ("DB_PASSWORD".to_string(), json!("hunter2")),
+            ("port".to_string(), json!(5432)),
+        ]
+        .into();
+        assert_eq!(
+            render_secret(&metadata(), &data).unwrap(),
+            "apiVersion: v1\nkind: Secret\nmetadata:\n  name: \"app-secrets\"\n  \
+             namespace: \"prod\"\n  labels:\n    \"app.kubernetes.io/name\": \"app\"\n\
+             type: Opaque\ndata:\n  \"DB_PASSWORD\": \"aHVudGVyMg==\"\n  \"port\": \"NTQzMg==\"\n"
+        );
+    }
+
+    #[test]
+    fn test_invalid_keys_are_all_reported() {
+        let data = [
+            ("ok".to_string(), json!("1")),
+            ("has space".to_string(), json!("2")),
+            ("a/b".to_string(), json!("3")),
+            ("\u{202e}gpj.exe".to_string(), json!("4")),
+        ]
+        .into();
+        let err = render_secret(&metadata(), &data).unwrap_err().to_string();
+        assert!(
+            err.contains("'has space'")
+                && err.contains("'a/b'")
+                && err.contains("'\\u{202e}gpj.exe'"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_key_clashes_are_all_reported() {
+        let secret = |pairs: &[(&str, &str)]| -> BTreeMap<String, Value> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), json!(v)))
+                .collect()
+        };
+        let merged = merge_data(vec![
+            ("prod/app", secret(&[("api_key", "k")])),
+            ("prod/db", secret(&[("password", "p")])),
+        ])
+        .unwrap();
+        assert_eq!(merged.len(), 2);
+
+        let err = merge_data(vec![
+            ("prod/app", secret(&[("password", "a"), ("user", "u")])),
+            ("prod/db", secret(&[("password", "b"), ("user", "v")])),
+            ("prod/cache\u{202e}", secret(&[("password", "c")])),
+        ])
+        .unwrap_err()
+        .to_string();
+        assert_eq!(
+            err,
+            "key(s) held by more than one secret: \
+             'password' ('prod/app', 'prod/db', 'prod/cache\\u{202e}'), \
+             'user' ('prod/app', 'prod/db')"
+        );
+    }
+
+    #[test]
+    fn test_invalid_metadata_is_rejected() {
+        let mut bad = metadata();
+        bad.labels
+            .push(("tier".to_string(), "x\n  evil: 1".to_string()));
+        assert!(render_secret(&bad, &BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_external_secret_references_without_values() {
+        let store = StoreRef {
+            name: "aws".to_string(),
+            kind: "ClusterSecretStore",
+            refresh_interval: "1h".to_string(),
+        };
+        let out = render_external_secret(
+            &metadata(),
+            &["prod/app".to_string(), "prod/db".to_string()],
+            &store,
+        )
+        .unwrap();
+        assert!(out.starts_with("apiVersion: external-secrets.io/v1\nkind: ExternalSecret\n"));
+        assert!(out.contains("    kind: \"ClusterSecretStore\"\n"));
+        assert!(out.contains(
+            "  dataFrom:\n    - extract:\n        key: \"prod/app\"\n    - extract:\n        key: \"prod/db\"\n"
+        ));
+    }
+}
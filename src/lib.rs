@@ -4,6 +4,36 @@
 //! them as a library lets integration tests exercise the real rendering and
 //! fetching code paths directly.
 
+#[cfg(unix)]
+pub mod agent;
 pub mod aws;
+pub mod browse;
+pub mod certs;
+pub mod ci;
 pub mod cli;
+pub mod clipboard;
 pub mod commands;
+pub mod compliance;
+pub mod drift;
+pub mod dsn;
+pub mod embedded;
+pub mod error;
+pub mod fuzzy;
+pub mod inspect;
+pub mod k8s;
+pub mod manifest;
+#[cfg(unix)]
+pub mod materialize;
+pub mod names;
+#[cfg(unix)]
+pub mod outputs;
+pub mod query;
+pub mod redact;
+pub mod scan;
+pub mod select;
+pub mod serve;
+pub mod terraform;
+pub mod tokens;
+pub mod tree;
+#[cfg(unix)]
+pub mod watch;
@@ -1,40 +1,502 @@
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use aws_sdk_secretsmanager::Client;
 use clap::Parser;
 
-use goldfinch::aws::{fetch_secret, fetch_secrets_concurrent, list_all_secrets};
-use goldfinch::cli::{Cli, Commands};
-use goldfinch::commands::{get_secret, list_keys, search_keys};
+#[cfg(unix)]
+use goldfinch::agent;
+use goldfinch::aws::{
+    describe_secrets_concurrent, fetch_secret, fetch_secret_string,
+    fetch_secret_strings_concurrent, fetch_secrets_concurrent, list_all_secrets,
+    list_secret_metadata, resolve_secret_names,
+};
+use goldfinch::browse;
+use goldfinch::ci;
+#[cfg(unix)]
+use goldfinch::cli::AgentCommand;
+use goldfinch::cli::{Cli, Commands, Report};
+use goldfinch::clipboard;
+use goldfinch::commands::{
+    get_secret, list_keys, lookup, metadata_matches, search_keys, show_matches, Sanitized,
+};
+use goldfinch::compliance::{self, RuleSet};
+use goldfinch::drift;
+use goldfinch::dsn::{self, DatabaseSecret};
+use goldfinch::embedded;
+use goldfinch::error::{error_kind, Error, ErrorKind};
+use goldfinch::fuzzy;
+use goldfinch::inspect;
+use goldfinch::k8s;
+use goldfinch::manifest::{self, Manifest};
+#[cfg(unix)]
+use goldfinch::materialize;
+use goldfinch::names;
+#[cfg(unix)]
+use goldfinch::outputs::Output;
+use goldfinch::query::{self, Query};
+use goldfinch::redact::{self, Redactor};
+use goldfinch::scan::{self, Needles};
+use goldfinch::serve;
+use goldfinch::terraform;
+use goldfinch::tree;
+#[cfg(unix)]
+use goldfinch::watch;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use std::time::Duration;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("Error: {err:?}");
+            error_kind(&err).map_or(ExitCode::FAILURE, |kind| ExitCode::from(kind.exit_code()))
+        }
+    }
+}
+
+async fn run() -> Result<()> {
     let cli = Cli::parse();
+    let ci = cli.format.ci();
+    if ci.is_some() && !matches!(cli.command, Commands::Get { .. }) {
+        bail!("CI output formats are only supported by `get`");
+    }
+    let format = cli.format.output();
 
     // Initialize AWS config and client
     let config = aws_config::load_from_env().await;
     let client = Client::new(&config);
 
     match &cli.command {
-        Commands::List => {
+        Commands::List { tree: false } => {
+            let secret_ids = list_all_secrets(&client).await?;
+            list_keys(&secret_ids, format)?;
+        }
+        Commands::List { tree: true } => {
+            let secret_ids = list_all_secrets(&client).await?;
+            tree::show_tree(&tree::build(&secret_ids, None, None), format)?;
+        }
+        Commands::Tree { root, depth } => {
             let secret_ids = list_all_secrets(&client).await?;
-            list_keys(&secret_ids, cli.format)?;
+            tree::show_tree(&tree::build(&secret_ids, root.as_deref(), *depth), format)?;
         }
-        Commands::Get { secret_name } => {
+        Commands::Browse { query } => {
+            let secret_ids = list_all_secrets(&client).await?;
+            let socket = agent_socket();
+            let picked = browse::run(secret_ids, query.as_deref().unwrap_or_default(), |name| {
+                let (client, socket) = (&client, socket.as_deref());
+                async move {
+                    match socket {
+                        #[cfg(unix)]
+                        Some(socket) => agent::get(socket, &name).await,
+                        _ => fetch_secret(client, &name).await,
+                    }
+                }
+            })
+            .await?;
+            if let Some(value) = picked {
+                browse::print_value(&value, format)?;
+            }
+        }
+        Commands::Copy {
+            secret_name,
+            key,
+            clear_after,
+        } => {
+            let secret_data = match agent_socket() {
+                #[cfg(unix)]
+                Some(socket) => agent::get(&socket, secret_name).await?,
+                _ => fetch_secret(&client, secret_name).await?,
+            };
+            let Some(value) = lookup(&secret_data, key) else {
+                bail!(
+                    "Secret '{}' has no key '{}'",
+                    Sanitized(secret_name),
+                    Sanitized(key)
+                );
+            };
+            let (method, scheduled) = clipboard::copy_and_clear(&value, *clear_after)?;
+            eprintln!(
+                "Copied '{}' from '{}' to the clipboard ({})",
+                Sanitized(key),
+                Sanitized(secret_name),
+                method.describe()
+            );
+            if scheduled {
+                eprintln!(
+                    "It will be cleared in {}s if it still holds the value",
+                    clear_after.as_secs()
+                );
+            } else if !clear_after.is_zero() {
+                eprintln!("It will not be cleared: a clipboard set over OSC 52 cannot be checked");
+            }
+        }
+        Commands::ClearClipboard { method, after_ms } => {
+            let method = clipboard::Method::from_name(method)
+                .with_context(|| format!("Unknown clipboard method '{}'", method))?;
+            let mut digest = String::new();
+            std::io::stdin().read_to_string(&mut digest)?;
+            tokio::time::sleep(Duration::from_millis(*after_ms)).await;
+            clipboard::clear_if_unchanged(method, digest.trim())?;
+        }
+        Commands::Get {
+            secret_name,
+            decode_embedded,
+        } => {
             // Direct fetch - no list needed (lazy load optimization)
-            let secret_data = fetch_secret(&client, secret_name).await?;
-            get_secret(&secret_data, cli.format)?;
+            let fetched = match agent_socket() {
+                #[cfg(unix)]
+                Some(socket) => agent::get(&socket, secret_name).await,
+                _ => fetch_secret(&client, secret_name).await,
+            };
+            let mut secret_data = match fetched {
+                Err(err) if error_kind(&err) == Some(ErrorKind::NotFound) => {
+                    // Suggestions are best effort: without ListSecrets the
+                    // original error stands alone.
+                    let cache = names::cache_path(
+                        config.region().map(|r| r.as_ref()),
+                        std::env::var("AWS_PROFILE").ok().as_deref(),
+                    );
+                    let known = names::cached_names(&client, cache.as_deref(), unix_now())
+                        .await
+                        .unwrap_or_default();
+                    let suggestions = names::suggestions(secret_name, &known);
+                    return Err(err.context(names::not_found_message(secret_name, &suggestions)));
+                }
+                fetched => fetched?,
+            };
+            if let Some(mode) = decode_embedded {
+                embedded::decode_embedded(&mut secret_data, *mode);
+            }
+            match ci {
+                Some(platform) => ci::export(platform, &secret_data)?,
+                None => get_secret(&secret_data, format)?,
+            }
+        }
+        Commands::Search {
+            query: Some(query),
+            decode_embedded,
+            ..
+        } => {
+            // Parse before any API call so a malformed query fails fast.
+            let parsed = Query::parse(query)?;
+            let records = query::search(&client, &parsed, *decode_embedded).await?;
+            query::require_matches(&records, query)?;
+            show_matches(&records, format)?;
+        }
+        Commands::Search {
+            pattern: Some(pattern),
+            names_only: true,
+            fuzzy: true,
+            limit,
+            ..
+        } => {
+            let secrets = list_secret_metadata(&client).await?;
+            fuzzy::show_ranked(
+                &fuzzy::rank_names(&secrets, pattern, *limit),
+                pattern,
+                format,
+            )?;
+        }
+        Commands::Search {
+            pattern: Some(pattern),
+            names_only: true,
+            ..
+        } => {
+            let secrets = list_secret_metadata(&client).await?;
+            let matches = metadata_matches(&secrets, pattern);
+            if matches.is_empty() {
+                bail!(Error::NoMatches(format!(
+                    "No secrets found matching pattern '{}'",
+                    pattern
+                )));
+            }
+            show_matches(&matches, format)?;
         }
-        Commands::Search { pattern } => {
+        Commands::Search {
+            pattern: Some(pattern),
+            decode_embedded,
+            fuzzy: true,
+            limit,
+            ..
+        } => {
             let secret_ids = list_all_secrets(&client).await?;
-            let outcome = fetch_secrets_concurrent(&client, &secret_ids).await?;
+            let mut outcome = fetch_secrets_concurrent(&client, &secret_ids).await?;
+            for (id, err) in &outcome.failures {
+                eprintln!("warning: skipping secret '{}': {}", id, err);
+            }
+            if let Some(mode) = decode_embedded {
+                embedded::decode_all(&mut outcome.secrets, *mode);
+            }
+            let results = fuzzy::rank_secrets(&outcome.secrets, pattern, *limit);
+            fuzzy::show_ranked(&results, pattern, format)?;
+        }
+        Commands::Search {
+            pattern,
+            decode_embedded,
+            ..
+        } => {
+            let pattern = pattern.as_deref().unwrap_or_default();
+            let mut outcome = match agent_socket() {
+                #[cfg(unix)]
+                Some(socket) => agent::search(&socket, pattern, *decode_embedded).await?,
+                _ => {
+                    let secret_ids = list_all_secrets(&client).await?;
+                    fetch_secrets_concurrent(&client, &secret_ids).await?
+                }
+            };
             // Report unreadable secrets on stderr so they are visible without
             // suppressing results for the ones that were readable.
             for (id, err) in &outcome.failures {
                 eprintln!("warning: skipping secret '{}': {}", id, err);
             }
-            search_keys(&outcome.secrets, pattern, cli.format)?;
+            if let Some(mode) = decode_embedded {
+                embedded::decode_all(&mut outcome.secrets, *mode);
+            }
+            search_keys(&outcome.secrets, pattern, format)?;
+        }
+        Commands::Inspect { secret_name } => {
+            let secret_string = fetch_secret_string(&client, secret_name).await?;
+            let inspection = inspect::inspect_secret(secret_name, &secret_string, unix_now());
+            inspect::show_inspection(&inspection, format)?;
+        }
+        Commands::Dsn { secret_name, style } => {
+            let secret_data = fetch_secret(&client, secret_name).await?;
+            let secret = DatabaseSecret::from_secret(&secret_data)
+                .with_context(|| format!("Secret '{}' is not an RDS-style secret", secret_name))?;
+            dsn::show_dsn(&secret, *style, format)?;
+        }
+        Commands::K8s {
+            secrets,
+            name,
+            namespace,
+            labels,
+            external_secret,
+            secret_store,
+            cluster_store,
+            refresh_interval,
+        } => {
+            let names = resolve_secret_names(&client, secrets).await?;
+            let metadata = k8s::Metadata {
+                name: name.clone(),
+                namespace: namespace.clone(),
+                labels: labels.clone(),
+            };
+            let manifest = match (external_secret, secret_store) {
+                (true, Some(store)) => {
+                    let store = k8s::StoreRef {
+                        name: store.clone(),
+                        kind: if *cluster_store {
+                            "ClusterSecretStore"
+                        } else {
+                            "SecretStore"
+                        },
+                        refresh_interval: refresh_interval.clone(),
+                    };
+                    k8s::render_external_secret(&metadata, &names, &store)?
+                }
+                _ => {
+                    let mut outcome = fetch_secrets_concurrent(&client, &names).await?;
+                    // Embedding a partial set would ship a Secret missing keys.
+                    if let Some((id, err)) = outcome.failures.first() {
+                        bail!("Failed to read secret '{}': {}", id, err);
+                    }
+                    let secrets = names
+                        .iter()
+                        .map(|name| {
+                            let data = outcome.secrets.remove(name).unwrap_or_default();
+                            (name.as_str(), data)
+                        })
+                        .collect();
+                    k8s::render_secret(&metadata, &k8s::merge_data(secrets)?)?
+                }
+            };
+            print!("{manifest}");
+        }
+        Commands::TfExternal => {
+            let query = terraform::Query::parse(std::io::stdin().lock())?;
+            let secret_data = fetch_secret(&client, &query.secret).await?;
+            terraform::show_result(&terraform::result_map(&query, &secret_data)?)?;
         }
+        Commands::Check { env, manifest } => {
+            // Parse the manifest before any API call so a typo fails fast.
+            let manifest = Manifest::load(manifest)?;
+            let specs = manifest.env(env)?;
+            let names: Vec<String> = specs.iter().map(|spec| spec.name.clone()).collect();
+            let outcome = fetch_secrets_concurrent(&client, &names).await?;
+            manifest::report_check(&manifest::check(env, specs, &outcome), format)?;
+        }
+        Commands::Drift { envs, identical } => {
+            if envs.len() < 2 {
+                bail!("--envs needs at least two environments");
+            }
+            if let Some(env) = envs.iter().find(|env| env.is_empty() || env.contains('/')) {
+                bail!("invalid environment '{}'", env);
+            }
+            let secret_ids = list_all_secrets(&client).await?;
+            let secret_ids = drift::names_in_envs(&secret_ids, envs);
+            let outcome = fetch_secrets_concurrent(&client, &secret_ids).await?;
+            drift::report_drift(&drift::drift(envs, &outcome, *identical), format)?;
+        }
+        Commands::Scan { paths, secrets } => {
+            let secret_ids = resolve_secret_names(&client, secrets).await?;
+            let outcome = fetch_secrets_concurrent(&client, &secret_ids).await?;
+            for (id, err) in &outcome.failures {
+                eprintln!("warning: skipping secret '{}': {}", id, err);
+            }
+            let needles = Needles::new(&outcome.secrets)?;
+            let scanned = scan::scan_paths(&needles, paths);
+            for (path, err) in &scanned.failures {
+                eprintln!("warning: could not scan '{}': {}", path.display(), err);
+            }
+            scan::report_leaks(&scanned.leaks, format)?;
+        }
+        Commands::Redact {
+            secrets,
+            allow_partial,
+        } => {
+            let secret_ids = resolve_secret_names(&client, secrets).await?;
+            let outcome = fetch_secrets_concurrent(&client, &secret_ids).await?;
+            // An unread secret's values would pass through unredacted.
+            if let Some((id, err)) = outcome.failures.first().filter(|_| !allow_partial) {
+                bail!(
+                    "Failed to read secret '{}' (pass --allow-partial to redact without it): {:#}",
+                    Sanitized(id),
+                    err
+                );
+            }
+            for (id, err) in &outcome.failures {
+                eprintln!(
+                    "warning: not redacting secret '{}': {:#}",
+                    Sanitized(id),
+                    err
+                );
+            }
+            let needles = Needles::for_redaction(&outcome.secrets)?;
+            redact::redact_stdio(&Redactor::new(&needles)?)?;
+        }
+        Commands::Serve {
+            port,
+            ttl,
+            token_file,
+        } => {
+            let token = serve::load_token(token_file.as_deref())?;
+            let server = serve::Server::with_client(token, *ttl, client);
+            serve::run(server, SocketAddr::from(([127, 0, 0, 1], *port))).await?;
+        }
+        #[cfg(unix)]
+        Commands::Materialize { secret_name, dir } => {
+            let secret_data = fetch_secret(&client, secret_name).await?;
+            let result = materialize::materialize(dir, &secret_data)?;
+            materialize::show_materialized(&result, format)?;
+        }
+        #[cfg(unix)]
+        Commands::Watch {
+            secrets,
+            interval,
+            on_change,
+            env_file,
+            template,
+            dir,
+        } => {
+            let names = resolve_secret_names(&client, secrets).await?;
+            let mut outputs: Vec<Output> = env_file.iter().cloned().map(Output::EnvFile).collect();
+            outputs.extend(template.iter().map(|(source, dest)| Output::Template {
+                source: source.clone(),
+                dest: dest.clone(),
+            }));
+            outputs.extend(dir.iter().cloned().map(Output::Dir));
+            watch::watch(
+                &client,
+                names,
+                *interval,
+                &outputs,
+                on_change.as_deref(),
+                format,
+            )
+            .await?;
+        }
+        #[cfg(unix)]
+        Commands::Agent { action } => match action {
+            AgentCommand::Start { socket, lifetime } => {
+                let socket = socket.clone().unwrap_or_else(agent::default_socket);
+                agent::run(agent::Agent::with_client(*lifetime, client), &socket).await?;
+            }
+            AgentCommand::Stop { socket } => {
+                let socket = socket
+                    .clone()
+                    .or_else(agent::socket_from_env)
+                    .unwrap_or_else(agent::default_socket);
+                agent::stop(&socket).await?;
+            }
+        },
+        Commands::Report { report } => match report {
+            Report::Compliance { rules } => {
+                // Parse the rules before any API call so a typo fails fast.
+                let rules = RuleSet::load(rules)?;
+                let mut secrets = list_secret_metadata(&client).await?;
+                if rules.needs_replication_status() {
+                    let names: Vec<String> = secrets.iter().map(|s| s.name.clone()).collect();
+                    let mut outcome = describe_secrets_concurrent(&client, &names).await?;
+                    for (id, err) in &outcome.failures {
+                        eprintln!("warning: could not describe secret '{}': {}", id, err);
+                    }
+                    for secret in &mut secrets {
+                        if let Some(described) = outcome.secrets.remove(&secret.name) {
+                            secret.replica_regions = described.replica_regions;
+                        }
+                    }
+                }
+                let findings = compliance::evaluate(&rules, &secrets, unix_now());
+                compliance::report_compliance(&findings, format)?;
+            }
+            Report::Expiring { within } => {
+                let now = unix_now();
+                let inspections = inspect_all_secrets(&client, now).await?;
+                let within = i64::try_from(within.as_secs()).unwrap_or(i64::MAX);
+                let expiring = inspect::expiring_certificates(&inspections, now, within);
+                inspect::report_expiring(&expiring, format)?;
+            }
+            Report::Tokens { within } => {
+                let now = unix_now();
+                let inspections = inspect_all_secrets(&client, now).await?;
+                let within = i64::try_from(within.as_secs()).unwrap_or(i64::MAX);
+                let expiring = inspect::expiring_tokens(&inspections, now, within);
+                inspect::report_expiring(&expiring, format)?;
+            }
+        },
     }
 
     Ok(())
 }
+
+/// Fetch and inspect every secret in the account, warning about (and
+/// skipping) any that cannot be read.
+async fn inspect_all_secrets(client: &Client, now: i64) -> Result<Vec<inspect::Inspection>> {
+    let secret_ids = list_all_secrets(client).await?;
+    let outcome = fetch_secret_strings_concurrent(client, &secret_ids).await?;
+    for (id, err) in &outcome.failures {
+        eprintln!("warning: skipping secret '{}': {}", id, err);
+    }
+    Ok(outcome
+        .secrets
+        .iter()
+        .map(|(id, secret_string)| inspect::inspect_secret(id, secret_string, now))
+        .collect())
+}
+
+/// The agent socket `get` and `search` should go through, if any.
+fn agent_socket() -> Option<std::path::PathBuf> {
+    #[cfg(unix)]
+    return agent::socket_from_env();
+    #[cfg(not(unix))]
+    None
+}
+
+fn unix_now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,436 @@
+//! `goldfinch.toml`: the secrets and keys a project needs, per environment,
+//! and `check`, which verifies them before a rollout.
+//!
+//! ```toml
+//! [[env.prod.secret]]
+//! name = "prod/app/db"
+//!
+//! [env.prod.secret.keys]
+//! username = {}
+//! port = { type = "integer" }
+//! password = { type = "string", regex = "^.{16,}$" }
+//! ```
+//!
+//! Every problem is collected, so one run lists everything a deploy would
+//! trip over. Values are never echoed in a problem's description.
+
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::aws::FetchOutcome;
+use crate::cli::OutputFormat;
+use crate::commands::{render_table, value_to_string, Sanitized};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawManifest {
+    #[serde(default)]
+    env: BTreeMap<String, RawEnv>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawEnv {
+    #[serde(default, rename = "secret")]
+    secrets: Vec<RawSecret>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSecret {
+    name: String,
+    #[serde(default)]
+    keys: BTreeMap<String, RawKey>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawKey {
+    #[serde(rename = "type")]
+    kind: Option<ValueType>,
+    regex: Option<String>,
+}
+
+/// The JSON type a key's value must have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Object,
+    Array,
+}
+
+impl ValueType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            ValueType::String => value.is_string(),
+            ValueType::Number => value.is_number(),
+            ValueType::Integer => value.is_i64() || value.is_u64(),
+            ValueType::Boolean => value.is_boolean(),
+            ValueType::Object => value.is_object(),
+            ValueType::Array => value.is_array(),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ValueType::String => "string",
+            ValueType::Number => "number",
+            ValueType::Integer => "integer",
+            ValueType::Boolean => "boolean",
+            ValueType::Object => "object",
+            ValueType::Array => "array",
+        }
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Constraints on one required key.
+#[derive(Debug, Default)]
+pub struct KeySpec {
+    pub kind: Option<ValueType>,
+    /// Matched against the value as `get --format plain` renders it.
+    pub regex: Option<Regex>,
+}
+
+/// One required secret.
+#[derive(Debug)]
+pub struct SecretSpec {
+    pub name: String,
+    pub keys: BTreeMap<String, KeySpec>,
+}
+
+#[derive(Debug)]
+pub struct Manifest {
+    pub envs: BTreeMap<String, Vec<SecretSpec>>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read manifest '{}'", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid manifest '{}'", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let raw: RawManifest = toml::from_str(text)?;
+        if raw.env.is_empty() {
+            bail!("manifest defines no [[env.<name>.secret]] entries");
+        }
+
+        let mut envs = BTreeMap::new();
+        for (env, raw_env) in raw.env {
+            let mut secrets = Vec::with_capacity(raw_env.secrets.len());
+            for raw_secret in raw_env.secrets {
+                if secrets
+                    .iter()
+                    .any(|s: &SecretSpec| s.name == raw_secret.name)
+                {
+                    bail!("env '{env}' lists secret '{}' twice", raw_secret.name);
+                }
+                let mut keys = BTreeMap::new();
+                for (key, raw_key) in raw_secret.keys {
+                    let regex = raw_key
+                        .regex
+                        .map(|pattern| {
+                            Regex::new(&pattern).with_context(|| {
+                                format!(
+                                    "invalid regex for '{}' key '{key}': '{pattern}'",
+                                    raw_secret.name
+                                )
+                            })
+                        })
+                        .transpose()?;
+                    keys.insert(
+                        key,
+                        KeySpec {
+                            kind: raw_key.kind,
+                            regex,
+                        },
+                    );
+                }
+                secrets.push(SecretSpec {
+                    name: raw_secret.name,
+                    keys,
+                });
+            }
+            envs.insert(env, secrets);
+        }
+        Ok(Manifest { envs })
+    }
+
+    /// The secrets required in `env`.
+    pub fn env(&self, env: &str) -> Result<&[SecretSpec]> {
+        match self.envs.get(env) {
+            Some(secrets) => Ok(secrets),
+            None => bail!(
+                "manifest has no environment '{env}' (defined: {})",
+                self.envs.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        }
+    }
+}
+
+/// One thing wrong with an environment.
+#[derive(Debug, Serialize)]
+pub struct Problem {
+    pub secret: String,
+    /// The key concerned, or `None` when the whole secret is.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub problem: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub env: String,
+    pub secrets_checked: usize,
+    pub keys_checked: usize,
+    pub problems: Vec<Problem>,
+}
+
+/// Check the fetched secrets against `specs`. Secrets in
+/// `outcome.failures` are reported as unreadable; everything else is checked
+/// key by key.
+pub fn check(env: &str, specs: &[SecretSpec], outcome: &FetchOutcome) -> CheckReport {
+    let mut problems = Vec::new();
+    let failures: BTreeMap<&str, &anyhow::Error> = outcome
+        .failures
+        .iter()
+        .map(|(id, err)| (id.as_str(), err))
+        .collect();
+
+    for spec in specs {
+        let Some(data) = outcome.secrets.get(&spec.name) else {
+            let problem = match failures.get(spec.name.as_str()) {
+                Some(err) => format!("cannot read secret: {err:#}"),
+                None => "secret was not fetched".to_string(),
+            };
+            problems.push(Problem {
+                secret: spec.name.clone(),
+                key: None,
+                problem,
+            });
+            continue;
+        };
+        for (key, key_spec) in &spec.keys {
+            if let Some(problem) = check_key(key_spec, data.get(key)) {
+                problems.push(Problem {
+                    secret: spec.name.clone(),
+                    key: Some(key.clone()),
+                    problem,
+                });
+            }
+        }
+    }
+
+    CheckReport {
+        env: env.to_string(),
+        secrets_checked: specs.len(),
+        keys_checked: specs.iter().map(|s| s.keys.len()).sum(),
+        problems,
+    }
+}
+
+fn check_key(spec: &KeySpec, value: Option<&Value>) -> Option<String> {
+    let Some(value) = value else {
+        return Some("missing key".to_string());
+    };
+    if let Some(kind) = spec.kind {
+        if !kind.matches(value) {
+            return Some(format!(
+                "expected {}, found {}",
+                kind.name(),
+                type_name(value)
+            ));
+        }
+    }
+    if let Some(regex) = &spec.regex {
+        if !regex.is_match(&value_to_string(value)) {
+            return Some(format!("value does not match regex {}", regex.as_str()));
+        }
+    }
+    None
+}
+
+pub fn report_check(report: &CheckReport, format: OutputFormat) -> Result<()> {
+    write_check(&mut io::stdout().lock(), report, format)
+}
+
+/// Render the report, then fail if there were any problems so `check` can
+/// gate a deploy.
+pub fn write_check<W: Write>(w: &mut W, report: &CheckReport, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(report)?)?;
+        }
+        OutputFormat::Plain if report.problems.is_empty() => {
+            writeln!(
+                w,
+                "{}: {} secret(s) and {} key(s) OK",
+                Sanitized(&report.env),
+                report.secrets_checked,
+                report.keys_checked
+            )?;
+        }
+        OutputFormat::Plain => {
+            let rows: Vec<Vec<String>> = report
+                .problems
+                .iter()
+                .map(|p| {
+                    vec![
+                        p.secret.clone(),
+                        p.key.clone().unwrap_or_else(|| "-".to_string()),
+                        p.problem.clone(),
+                    ]
+                })
+                .collect();
+            render_table(w, &["SECRET", "KEY", "PROBLEM"], &rows)?;
+        }
+    }
+
+    if !report.problems.is_empty() {
+        bail!(
+            "{} problem(s) in environment '{}'",
+            report.problems.len(),
+            report.env
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::anyhow;
+    use serde_json::json;
+
+    const MANIFEST: &str = r#"
+        [[env.prod.secret]]
+        name = "prod/app/db"
+
+        [env.prod.secret.keys]
+        username = {}
+        port = { type = "integer" }
+        password = { type = "string", regex = "^.{16,}$" }
+
+        [[env.prod.secret]]
+        name = "prod/app/api"
+
+        [[env.dev.secret]]
+        name = "dev/app/db"
+    "#;
+
+    fn outcome(secrets: &[(&str, Value)], failures: &[&str]) -> FetchOutcome {
+        FetchOutcome {
+            secrets: secrets
+                .iter()
+                .map(|(name, data)| {
+                    let data: BTreeMap<String, Value> =
+                        serde_json::from_value(data.clone()).unwrap();
+                    (name.to_string(), data)
+                })
+                .collect(),
+            failures: failures
+                .iter()
+                .map(|name| {
+                    let err = anyhow!("AccessDeniedException")
+                        .context(format!("Failed to fetch secret '{name}'"));
+                    (name.to_string(), err)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let prod = manifest.env("prod").unwrap();
+        assert_eq!(prod.len(), 2);
+        assert_eq!(prod[0].keys.len(), 3);
+        assert_eq!(prod[0].keys["port"].kind, Some(ValueType::Integer));
+        let err = manifest.env("staging").unwrap_err().to_string();
+        assert!(err.ends_with("(defined: dev, prod)"), "{err}");
+
+        assert!(Manifest::parse(
+            "[[env.prod.secret]]\nname = \"a\"\nkeys.x = { type = \"uuid\" }\n"
+        )
+        .is_err());
+        assert!(
+            Manifest::parse("[[env.prod.secret]]\nname = \"a\"\nkeys.x = { regex = \"(\" }\n")
+                .is_err()
+        );
+        assert!(Manifest::parse(
+            "[[env.prod.secret]]\nname = \"a\"\n[[env.prod.secret]]\nname = \"a\"\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_check_reports_every_problem() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let fetched = outcome(
+            &[(
+                "prod/app/db",
+                json!({"port": "5432", "password": "short-secret"}),
+            )],
+            &["prod/app/api"],
+        );
+        let report = check("prod", manifest.env("prod").unwrap(), &fetched);
+        let problems: Vec<(Option<&str>, &str)> = report
+            .problems
+            .iter()
+            .map(|p| (p.key.as_deref(), p.problem.as_str()))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                (Some("password"), "value does not match regex ^.{16,}$"),
+                (Some("port"), "expected integer, found string"),
+                (Some("username"), "missing key"),
+                (
+                    None,
+                    "cannot read secret: Failed to fetch secret 'prod/app/api': AccessDeniedException"
+                ),
+            ]
+        );
+        assert!(!format!("{problems:?}").contains("short-secret"));
+
+        let mut out = Vec::new();
+        let err = write_check(&mut out, &report, OutputFormat::Plain).unwrap_err();
+        assert_eq!(err.to_string(), "4 problem(s) in environment 'prod'");
+        assert!(String::from_utf8(out).unwrap().starts_with("SECRET"));
+    }
+
+    #[test]
+    fn test_check_passes() {
+        let manifest = Manifest::parse(MANIFEST).unwrap();
+        let fetched = outcome(&[("dev/app/db", json!({}))], &[]);
+        let report = check("dev", manifest.env("dev").unwrap(), &fetched);
+        let mut out = Vec::new();
+        write_check(&mut out, &report, OutputFormat::Plain).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "dev: 1 secret(s) and 0 key(s) OK\n"
+        );
+    }
+}
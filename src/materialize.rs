@@ -0,0 +1,295 @@
+//! Writing secret values to files: atomically, with restrictive modes, and
+//! under file names that a key can never steer outside the target directory.
+//!
+//! Key names are attacker-influenced (anyone with `PutSecretValue` picks them),
+//! so a key becomes a file name only after [`safe_file_name`] has reduced it
+//! to a single, non-hidden path component.
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cli::OutputFormat;
+use crate::commands::{value_to_string, Sanitized};
+
+/// Mode of each file written into a secrets directory.
+pub const FILE_MODE: u32 = 0o400;
+
+/// Mode of a secrets directory goldfinch creates.
+const DIR_MODE: u32 = 0o700;
+
+/// Names of the files written by the previous run, so stale ones can be
+/// removed without touching anything else in a shared directory. Sanitized
+/// names never start with `.`, so no key can collide with it.
+const MANIFEST: &str = ".goldfinch-files";
+
+/// Longest file name most filesystems accept.
+const MAX_NAME_LEN: usize = 255;
+
+/// Numbers [`write_atomic`]'s temporary files within this process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Map a key to a file name that is a single, visible path component.
+///
+/// Anything outside `[A-Za-z0-9._-]` (including `/`, `\` and NUL) becomes
+/// `_`, and a leading `.` becomes `_`, which rules out `.`, `..` and hidden
+/// files. Returns `None` for an empty or over-long key.
+pub fn safe_file_name(key: &str) -> Option<String> {
+    let mut name: String = key
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if name.starts_with('.') {
+        name.replace_range(..1, "_");
+    }
+    (!name.is_empty() && name.len() <= MAX_NAME_LEN).then_some(name)
+}
+
+/// Write `contents` to `path` atomically: a temporary file in the same
+/// directory is written, synced and renamed over `path`. Readers see either
+/// the old file or the new one, and a symlink at `path` is replaced rather
+/// than followed.
+///
+/// The temporary name does not include the file name, so any name up to
+/// [`MAX_NAME_LEN`] can be written.
+pub fn write_atomic(path: &Path, contents: &[u8], mode: u32) -> Result<()> {
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if path.file_name().is_none() {
+        bail!("'{}' is not a file path", path.display());
+    }
+    let tmp = dir.join(format!(
+        ".goldfinch-tmp-{}-{}",
+        std::process::id(),
+        TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let write = || -> Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&tmp)?;
+        file.write_all(contents)?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    };
+    write().map_err(|err| {
+        let _ = fs::remove_file(&tmp);
+        err.context(format!("Failed to write '{}'", path.display()))
+    })
+}
+
+/// What [`materialize`] changed.
+#[derive(Debug, Default, Serialize)]
+pub struct Materialized {
+    /// File names written, one per key.
+    pub written: Vec<String>,
+    /// Files from the previous run whose keys are gone.
+    pub removed: Vec<String>,
+}
+
+/// Write one file per key of `data` into `dir` (created `0700` if missing),
+/// each with mode [`FILE_MODE`], and remove files a previous run wrote for
+/// keys that no longer exist.
+///
+/// Fails before writing anything if two keys sanitize to the same file name.
+pub fn materialize(dir: &Path, data: &BTreeMap<String, Value>) -> Result<Materialized> {
+    let mut files: BTreeMap<String, &Value> = BTreeMap::new();
+    for (key, value) in data {
+        let Some(name) = safe_file_name(key) else {
+            bail!("key '{}' cannot be used as a file name", key.escape_debug());
+        };
+        if files.insert(name.clone(), value).is_some() {
+            bail!(
+                "two keys map to the file name '{}'; rename one of them",
+                name.escape_debug()
+            );
+        }
+    }
+
+    if !dir.exists() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(DIR_MODE)
+            .create(dir)
+            .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    }
+
+    let mut result = Materialized::default();
+    for (name, value) in &files {
+        write_atomic(
+            &dir.join(name),
+            value_to_string(value).as_bytes(),
+            FILE_MODE,
+        )?;
+        result.written.push(name.clone());
+    }
+
+    let manifest = dir.join(MANIFEST);
+    let previous = fs::read_to_string(&manifest).unwrap_or_default();
+    for name in previous.lines() {
+        // Re-sanitize: the manifest lives in a directory others may write to.
+        if safe_file_name(name).as_deref() != Some(name) || files.contains_key(name) {
+            continue;
+        }
+        match fs::remove_file(dir.join(name)) {
+            Ok(()) => result.removed.push(name.to_string()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to remove stale '{name}'"))
+            }
+        }
+    }
+
+    let names: BTreeSet<&String> = files.keys().collect();
+    let listing: String = names.iter().map(|name| format!("{name}\n")).collect();
+    write_atomic(&manifest, listing.as_bytes(), 0o600)?;
+    Ok(result)
+}
+
+pub fn show_materialized(result: &Materialized, format: OutputFormat) -> Result<()> {
+    write_materialized(&mut io::stdout().lock(), result, format)
+}
+
+pub fn write_materialized<W: Write>(
+    w: &mut W,
+    result: &Materialized,
+    format: OutputFormat,
+) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(result)?)?;
+        }
+        OutputFormat::Plain => {
+            for name in &result.written {
+                writeln!(w, "wrote {}", Sanitized(name))?;
+            }
+            for name in &result.removed {
+                writeln!(w, "removed {}", Sanitized(name))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("goldfinch-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn data(pairs: &[(&str, Value)]) -> BTreeMap<String, Value> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn test_safe_file_name() {
+        assert_eq!(
+            safe_file_name("db_password").as_deref(),
+            Some("db_password")
+        );
+        assert_eq!(
+            safe_file_name("../../etc/passwd").as_deref(),
+            Some("_._.._etc_passwd")
+        );
+        assert_eq!(safe_file_name("..").as_deref(), Some("_."));
+        assert_eq!(safe_file_name(".env").as_deref(), Some("_env"));
+        assert_eq!(safe_file_name("a\0b\\c").as_deref(), Some("a_b_c"));
+        assert_eq!(safe_file_name(""), None);
+        assert_eq!(safe_file_name(&"x".repeat(256)), None);
+    }
+
+    #[test]
+    fn test_materialize_writes_read_only_files_and_removes_stale_ones() {
+        let dir = temp_dir("materialize");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("unrelated"), "keep me").unwrap();
+
+        let first = materialize(
+            &dir,
+            &data(&[
+                ("user", json!("app")),
+                ("port", json!(5432)),
+                ("../escape", json!("x")),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(first.written, ["_._escape", "port", "user"]);
+        assert_eq!(fs::read_to_string(dir.join("port")).unwrap(), "5432");
+        let mode = fs::metadata(dir.join("user")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, FILE_MODE);
+        assert!(!dir.parent().unwrap().join("escape").exists());
+
+        let second = materialize(&dir, &data(&[("user", json!("app2"))])).unwrap();
+        assert_eq!(second.removed, ["_._escape", "port"]);
+        assert_eq!(fs::read_to_string(dir.join("user")).unwrap(), "app2");
+        assert!(dir.join("unrelated").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_colliding_keys_are_refused_before_writing() {
+        let dir = temp_dir("collide");
+        let err =
+            materialize(&dir, &data(&[("a/b", json!("1")), ("a_b", json!("2"))])).unwrap_err();
+        assert!(err.to_string().contains("two keys map to"));
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn test_longest_accepted_name_can_be_written() {
+        let dir = temp_dir("longname");
+        let longest = "k".repeat(MAX_NAME_LEN);
+        let written = materialize(&dir, &data(&[(&longest, json!("v"))])).unwrap();
+        assert_eq!(written.written, [longest.clone()]);
+        assert_eq!(fs::read_to_string(dir.join(&longest)).unwrap(), "v");
+
+        let err =
+            materialize(&dir, &data(&[(&"k".repeat(MAX_NAME_LEN + 1), json!("v"))])).unwrap_err();
+        assert!(err.to_string().contains("cannot be used as a file name"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_write_atomic_replaces_symlink_instead_of_following_it() {
+        let dir = temp_dir("symlink");
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("target");
+        fs::write(&target, "original").unwrap();
+        std::os::unix::fs::symlink(&target, dir.join("link")).unwrap();
+
+        write_atomic(&dir.join("link"), b"new", 0o600).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+        assert!(!fs::symlink_metadata(dir.join("link"))
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,211 @@
+//! "Did you mean" suggestions for secret names, backed by a short-lived
+//! on-disk cache of the account's names.
+//!
+//! The cache lives under `$XDG_CACHE_HOME/goldfinch` (or `~/.cache/goldfinch`),
+//! one file per region and profile, readable only by the owner. It holds
+//! names only, never values, and is refreshed after [`CACHE_TTL_SECS`].
+
+use anyhow::Result;
+use aws_sdk_secretsmanager::Client;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::aws::list_all_secrets;
+use crate::commands::Sanitized;
+use crate::fuzzy::edit_distance;
+
+/// How long a cached name list is used before ListSecrets is called again.
+pub const CACHE_TTL_SECS: i64 = 300;
+
+/// Most suggestions offered.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Serialize, Deserialize)]
+struct CachedNames {
+    listed_at: i64,
+    names: Vec<String>,
+}
+
+/// The cache file for names listed in `region` under `profile`.
+pub fn cache_path(region: Option<&str>, profile: Option<&str>) -> Option<PathBuf> {
+    let dir = match std::env::var_os("XDG_CACHE_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            PathBuf::from(std::env::var_os("HOME").filter(|home| !home.is_empty())?).join(".cache")
+        }
+    };
+    let key = format!(
+        "{}\n{}",
+        region.unwrap_or_default(),
+        profile.unwrap_or_default()
+    );
+    let digest = Sha256::digest(key.as_bytes());
+    let hex: String = digest[..8].iter().map(|b| format!("{b:02x}")).collect();
+    Some(dir.join("goldfinch").join(format!("names-{hex}.json")))
+}
+
+/// The cached names, if the cache exists and is younger than the TTL.
+pub fn load(path: &Path, now: i64) -> Option<Vec<String>> {
+    let cached: CachedNames = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+    (0..CACHE_TTL_SECS)
+        .contains(&(now - cached.listed_at))
+        .then_some(cached.names)
+}
+
+/// Write `names` to the cache, creating its directory (0700) and file
+/// (0600).
+pub fn store(path: &Path, names: &[String], now: i64) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        let mut builder = fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let cached = CachedNames {
+        listed_at: now,
+        names: names.to_vec(),
+    };
+    options
+        .open(path)?
+        .write_all(&serde_json::to_vec(&cached)?)?;
+    Ok(())
+}
+
+/// Every secret name, from the cache when it is fresh and from ListSecrets
+/// otherwise. A cache that cannot be written is not an error.
+pub async fn cached_names(client: &Client, path: Option<&Path>, now: i64) -> Result<Vec<String>> {
+    if let Some(names) = path.and_then(|path| load(path, now)) {
+        return Ok(names);
+    }
+    let names = list_all_secrets(client).await?;
+    if let Some(path) = path {
+        let _ = store(path, &names, now);
+    }
+    Ok(names)
+}
+
+/// The form names are compared in: lowercase, with `-`, `_` and `/` all
+/// treated as the same separator.
+fn comparable(name: &str) -> Vec<char> {
+    name.chars()
+        .flat_map(char::to_lowercase)
+        .map(|c| if matches!(c, '_' | '/') { '-' } else { c })
+        .collect()
+}
+
+/// The names in `names` closest to `wanted`, best first. Names that differ
+/// only in case or separators come first; others must be within an edit
+/// distance of a third of the name's length (at least 2).
+pub fn suggestions(wanted: &str, names: &[String]) -> Vec<String> {
+    let target = comparable(wanted);
+    let limit = (target.len() / 3).max(2);
+    let mut close: Vec<(usize, &String)> = names
+        .iter()
+        .filter(|name| name.as_str() != wanted)
+        .filter_map(|name| {
+            let candidate = comparable(name);
+            if candidate.len().abs_diff(target.len()) > limit {
+                return None;
+            }
+            let distance = edit_distance(&target, &candidate);
+            (distance <= limit).then_some((distance, name))
+        })
+        .collect();
+    close.sort();
+    close
+        .into_iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(_, name)| name.clone())
+        .collect()
+}
+
+/// The not-found message for `wanted`, with any suggestions on their own
+/// lines.
+pub fn not_found_message(wanted: &str, suggestions: &[String]) -> String {
+    let mut message = format!("Secret '{}' not found", Sanitized(wanted));
+    if !suggestions.is_empty() {
+        message.push_str("; did you mean:");
+        for name in suggestions {
+            message.push_str(&format!("\n    {}", Sanitized(name)));
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        [
+            "payments-api/stripe_key",
+            "prod/db",
+            "Prod/DB-Replica",
+            "prod/web",
+            "staging/db",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    #[test]
+    fn test_suggestions_ignore_case_and_separators() {
+        assert_eq!(
+            suggestions("payments_api-stripe-key", &names()),
+            ["payments-api/stripe_key"]
+        );
+        assert_eq!(
+            suggestions("prod/db_replica", &names()),
+            ["Prod/DB-Replica"]
+        );
+        assert_eq!(suggestions("prod/bd", &names()), ["prod/db"]);
+        assert_eq!(suggestions("prod/wb", &names()), ["prod/db", "prod/web"]);
+        assert!(suggestions("something/else", &names()).is_empty());
+        assert!(!suggestions("prod/db", &names()).contains(&"prod/db".to_string()));
+    }
+
+    #[test]
+    fn test_not_found_message_is_sanitized() {
+        assert_eq!(
+            not_found_message("prod/bd", &["prod/db\n".to_string()]),
+            "Secret 'prod/bd' not found; did you mean:\n    prod/db\\x0a"
+        );
+        assert_eq!(not_found_message("x", &[]), "Secret 'x' not found");
+    }
+
+    #[test]
+    fn test_cache_round_trip_and_expiry() {
+        let dir = std::env::temp_dir().join(format!("goldfinch-names-{}", std::process::id()));
+        let path = dir.join("goldfinch").join("names.json");
+        store(&path, &names(), 1_000).unwrap();
+        assert_eq!(load(&path, 1_000 + CACHE_TTL_SECS - 1), Some(names()));
+        assert_eq!(load(&path, 1_000 + CACHE_TTL_SECS), None);
+        assert_eq!(load(&path, 999), None);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_path_depends_on_region_and_profile() {
+        let a = cache_path(Some("us-east-1"), None);
+        let b = cache_path(Some("eu-west-1"), None);
+        let c = cache_path(Some("us-east-1"), Some("prod"));
+        if a.is_some() {
+            assert_ne!(a, b);
+            assert_ne!(a, c);
+        }
+    }
+}
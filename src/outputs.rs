@@ -0,0 +1,151 @@
+//! Files rendered from secret data: dotenv-style env files and `{{ key }}`
+//! templates. Both are written with [`write_atomic`] and mode `0600`.
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::commands::value_to_string;
+use crate::materialize::{materialize, write_atomic};
+
+/// Mode of rendered env files and templates.
+const RENDERED_MODE: u32 = 0o600;
+
+/// Whether `name` is a portable environment variable name.
+pub fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Render `KEY="value"` lines. Values are double-quoted with `\`, `"`, `$`
+/// and `` ` `` escaped and newlines written as `\n`, so a value can neither
+/// end its line nor expand when the file is sourced. Keys that are not valid
+/// variable names are skipped and returned.
+pub fn render_env_file(data: &BTreeMap<String, Value>) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut skipped = Vec::new();
+    for (key, value) in data {
+        if !is_env_name(key) {
+            skipped.push(key.clone());
+            continue;
+        }
+        let mut quoted = String::new();
+        for c in value_to_string(value).chars() {
+            match c {
+                '\\' | '"' | '$' | '`' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                '\n' => quoted.push_str("\\n"),
+                '\r' => quoted.push_str("\\r"),
+                c => quoted.push(c),
+            }
+        }
+        out.push_str(&format!("{key}=\"{quoted}\"\n"));
+    }
+    (out, skipped)
+}
+
+/// Replace each `{{ key }}` in `template` with that key's value. An unknown
+/// key or an unclosed `{{` is an error, so a typo never renders as an empty
+/// credential.
+pub fn render_template(template: &str, data: &BTreeMap<String, Value>) -> Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            bail!("unclosed '{{{{' in template");
+        };
+        let key = after[..end].trim();
+        let value = data
+            .get(key)
+            .with_context(|| format!("template references unknown key '{}'", key.escape_debug()))?;
+        out.push_str(&value_to_string(value));
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// A file or directory kept in sync with secret data.
+#[derive(Debug, Clone)]
+pub enum Output {
+    EnvFile(PathBuf),
+    Template { source: PathBuf, dest: PathBuf },
+    Dir(PathBuf),
+}
+
+impl Output {
+    pub fn write(&self, data: &BTreeMap<String, Value>) -> Result<()> {
+        match self {
+            Output::EnvFile(path) => {
+                let (contents, skipped) = render_env_file(data);
+                for key in skipped {
+                    eprintln!(
+                        "warning: key '{}' is not a valid variable name; not written to '{}'",
+                        key.escape_debug(),
+                        path.display()
+                    );
+                }
+                write_atomic(path, contents.as_bytes(), RENDERED_MODE)
+            }
+            Output::Template { source, dest } => {
+                let template = fs::read_to_string(source)
+                    .with_context(|| format!("Failed to read template '{}'", source.display()))?;
+                let rendered = render_template(&template, data)
+                    .with_context(|| format!("Failed to render '{}'", source.display()))?;
+                write_atomic(dest, rendered.as_bytes(), RENDERED_MODE)
+            }
+            Output::Dir(dir) => materialize(dir, data).map(|_| ()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Output::EnvFile(path) | Output::Dir(path) => path,
+            Output::Template { dest, .. } => dest,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data() -> BTreeMap<String, Value> {
+        [
+            ("DB_PASSWORD", json!("p\"w$HOME`x`\nINJECTED=1")),
+            ("PORT", json!(5432)),
+            ("not-a-var", json!("x")),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+    }
+
+    #[test]
+    fn test_env_file_escapes_values() {
+        let (out, skipped) = render_env_file(&data());
+        assert_eq!(
+            out,
+            "DB_PASSWORD=\"p\\\"w\\$HOME\\`x\\`\\nINJECTED=1\"\nPORT=\"5432\"\n"
+        );
+        assert_eq!(skipped, ["not-a-var"]);
+    }
+
+    #[test]
+    fn test_template() {
+        let rendered = render_template("port={{PORT}} dash={{ not-a-var }}", &data()).unwrap();
+        assert_eq!(rendered, "port=5432 dash=x");
+        assert!(render_template("{{ missing }}", &data()).is_err());
+        assert!(render_template("{{ PORT", &data()).is_err());
+    }
+}
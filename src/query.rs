@@ -0,0 +1,644 @@
+//! `search --query`: a small boolean query language.
+//!
+//! ```text
+//! key:password AND tag:env=prod AND NOT name:legacy/*
+//! (name:prod/* OR name:shared/*) AND value:"BEGIN RSA"
+//! ```
+//!
+//! A term is `FIELD:PATTERN` with FIELD one of `name`, `key` (the key path,
+//! as in `db.password`), `value`, `tag` (`tag:KEY` or `tag:KEY=VALUE`) and
+//! `description`. A pattern with `*` or `?` is a glob over the whole text;
+//! otherwise it is a substring match, except for tags, which match exactly.
+//! Patterns with spaces or parentheses are written in double quotes.
+//! Terms combine with `AND`, `OR`, `NOT` and parentheses; `NOT` binds
+//! tightest and `AND` binds tighter than `OR`.
+//!
+//! `name`, `tag` and `description` terms are decided from ListSecrets output,
+//! so every secret they rule out is never fetched. A query without `key` or
+//! `value` terms makes no GetSecretValue call at all.
+
+use anyhow::{bail, Result};
+use aws_sdk_secretsmanager::Client;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Range;
+
+use crate::aws::{fetch_secrets_concurrent, list_secret_metadata, SecretMetadata};
+use crate::cli::{KeyValue, PathSegment};
+use crate::commands::{key_path, value_to_string, walk_leaves};
+use crate::embedded::{decode_all, Embedded};
+use crate::error::Error;
+use crate::select::{glob_match, is_pattern};
+
+const FIELDS: &str = "name, key, value, tag, description";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Name,
+    Key,
+    Value,
+    Tag,
+    Description,
+}
+
+impl Field {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "name" => Some(Field::Name),
+            "key" => Some(Field::Key),
+            "value" => Some(Field::Value),
+            "tag" => Some(Field::Tag),
+            "description" => Some(Field::Description),
+            _ => None,
+        }
+    }
+}
+
+/// A glob when it contains `*` or `?`, a substring (or, for tags, exact)
+/// match otherwise.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern(pub String);
+
+impl Pattern {
+    fn contained_in(&self, text: &str) -> bool {
+        if is_pattern(&self.0) {
+            glob_match(&self.0, text)
+        } else {
+            text.contains(self.0.as_str())
+        }
+    }
+
+    fn equals(&self, text: &str) -> bool {
+        if is_pattern(&self.0) {
+            glob_match(&self.0, text)
+        } else {
+            text == self.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    Name(Pattern),
+    Key(Pattern),
+    Value(Pattern),
+    Tag {
+        key: Pattern,
+        value: Option<Pattern>,
+    },
+    Description(Pattern),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Term(Term),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A parse error pointing at the offending part of the query.
+#[derive(Debug)]
+pub struct QueryError {
+    pub message: String,
+    pub query: String,
+    /// Byte range of the offending text.
+    pub span: Range<usize>,
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let start = self.query[..self.span.start].chars().count();
+        let width = self.query[self.span.clone()].chars().count().max(1);
+        write!(
+            f,
+            "invalid query: {}\n  {}\n  {}{}",
+            self.message,
+            self.query,
+            " ".repeat(start),
+            "^".repeat(width)
+        )
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Term(Term),
+}
+
+impl Token {
+    fn describe(&self) -> &'static str {
+        match self {
+            Token::LParen => "'('",
+            Token::RParen => "')'",
+            Token::And => "AND",
+            Token::Or => "OR",
+            Token::Not => "NOT",
+            Token::Term(_) => "a term",
+        }
+    }
+}
+
+struct Lexer<'a> {
+    query: &'a str,
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn error(&self, message: String, span: Range<usize>) -> QueryError {
+        QueryError {
+            message,
+            query: self.query.to_string(),
+            span,
+        }
+    }
+
+    fn peek_char(&self) -> Option<char> {
+        self.query[self.pos..].chars().next()
+    }
+
+    fn tokens(mut self) -> Result<Vec<(Token, Range<usize>)>, QueryError> {
+        let mut tokens = Vec::new();
+        loop {
+            while self.peek_char().is_some_and(char::is_whitespace) {
+                self.pos += self.peek_char().map_or(0, char::len_utf8);
+            }
+            let start = self.pos;
+            let Some(c) = self.peek_char() else {
+                return Ok(tokens);
+            };
+            let token = match c {
+                '(' => {
+                    self.pos += 1;
+                    Token::LParen
+                }
+                ')' => {
+                    self.pos += 1;
+                    Token::RParen
+                }
+                _ => self.word()?,
+            };
+            tokens.push((token, start..self.pos));
+        }
+    }
+
+    /// A keyword or a `FIELD:PATTERN` term.
+    fn word(&mut self) -> Result<Token, QueryError> {
+        let start = self.pos;
+        let rest = &self.query[start..];
+        let ident_len = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let ident = &rest[..ident_len];
+
+        if !rest[ident_len..].starts_with(':') {
+            let word_len = rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(rest.len());
+            let word = &rest[..word_len];
+            self.pos += word_len;
+            return match word.to_ascii_uppercase().as_str() {
+                "AND" => Ok(Token::And),
+                "OR" => Ok(Token::Or),
+                "NOT" => Ok(Token::Not),
+                _ => Err(self.error(
+                    format!("expected FIELD:PATTERN, found '{word}' (fields: {FIELDS})"),
+                    start..self.pos,
+                )),
+            };
+        }
+
+        let Some(field) = Field::parse(&ident.to_ascii_lowercase()) else {
+            return Err(self.error(
+                format!("unknown field '{ident}' (fields: {FIELDS})"),
+                start..start + ident_len,
+            ));
+        };
+        self.pos += ident_len + 1;
+        let pattern = self.pattern()?;
+        if pattern.is_empty() {
+            return Err(self.error(format!("missing pattern after '{ident}:'"), start..self.pos));
+        }
+
+        let term = match field {
+            Field::Name => Term::Name(Pattern(pattern)),
+            Field::Key => Term::Key(Pattern(pattern)),
+            Field::Value => Term::Value(Pattern(pattern)),
+            Field::Description => Term::Description(Pattern(pattern)),
+            Field::Tag => match pattern.split_once('=') {
+                Some((key, value)) => Term::Tag {
+                    key: Pattern(key.to_string()),
+                    value: Some(Pattern(value.to_string())),
+                },
+                None => Term::Tag {
+                    key: Pattern(pattern),
+                    value: None,
+                },
+            },
+        };
+        Ok(Token::Term(term))
+    }
+
+    /// A double-quoted string (with `\"` and `\\` escapes) or a run of
+    /// characters up to whitespace or a parenthesis.
+    fn pattern(&mut self) -> Result<String, QueryError> {
+        let start = self.pos;
+        let rest = &self.query[start..];
+        if !rest.starts_with('"') {
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c == '(' || c == ')')
+                .unwrap_or(rest.len());
+            self.pos += len;
+            return Ok(rest[..len].to_string());
+        }
+
+        let mut out = String::new();
+        let mut chars = rest.char_indices().skip(1);
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => out.push(escaped),
+                    None => break,
+                },
+                c => out.push(c),
+            }
+        }
+        Err(self.error("unclosed '\"'".to_string(), start..self.query.len()))
+    }
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    tokens: Vec<(Token, Range<usize>)>,
+    next: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: String, span: Range<usize>) -> QueryError {
+        QueryError {
+            message,
+            query: self.query.to_string(),
+            span,
+        }
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.next).map(|(token, _)| token)
+    }
+
+    fn span(&self) -> Range<usize> {
+        match self.tokens.get(self.next) {
+            Some((_, span)) => span.clone(),
+            None => self.query.len()..self.query.len(),
+        }
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.and()?;
+        while self.eat(&Token::Or) {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, QueryError> {
+        let mut expr = self.unary()?;
+        while self.eat(&Token::And) {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, QueryError> {
+        let span = self.span();
+        match self.tokens.get(self.next).map(|(token, _)| token.clone()) {
+            Some(Token::Not) => {
+                self.next += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.next += 1;
+                let expr = self.or()?;
+                if !self.eat(&Token::RParen) {
+                    return Err(self.error("unclosed '('".to_string(), span));
+                }
+                Ok(expr)
+            }
+            Some(Token::Term(term)) => {
+                self.next += 1;
+                Ok(Expr::Term(term))
+            }
+            Some(other) => {
+                Err(self.error(format!("expected a term, found {}", other.describe()), span))
+            }
+            None => Err(self.error("expected a term, found end of query".to_string(), span)),
+        }
+    }
+}
+
+/// A parsed query.
+#[derive(Debug)]
+pub struct Query {
+    pub expr: Expr,
+}
+
+impl Query {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let tokens = Lexer { query, pos: 0 }.tokens()?;
+        let mut parser = Parser {
+            query,
+            tokens,
+            next: 0,
+        };
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            let message = match token {
+                Token::RParen => "unmatched ')'".to_string(),
+                other => format!(
+                    "expected AND, OR or end of query, found {}",
+                    other.describe()
+                ),
+            };
+            return Err(parser.error(message, parser.span()));
+        }
+        Ok(Query { expr })
+    }
+
+    /// Whether the query has `key` or `value` terms, which need secret
+    /// values.
+    pub fn needs_values(&self) -> bool {
+        fn walk(expr: &Expr) -> bool {
+            match expr {
+                Expr::Term(term) => matches!(term, Term::Key(_) | Term::Value(_)),
+                Expr::Not(inner) => walk(inner),
+                Expr::And(a, b) | Expr::Or(a, b) => walk(a) || walk(b),
+            }
+        }
+        walk(&self.expr)
+    }
+
+    /// Whether `secret` may match: `false` only when its metadata alone
+    /// rules it out, whatever its values are.
+    pub fn may_match(&self, secret: &SecretMetadata) -> bool {
+        eval(&self.expr, secret, None) != Some(false)
+    }
+
+    /// Whether `secret` matches a query without `key` or `value` terms.
+    pub fn matches_secret(&self, secret: &SecretMetadata) -> bool {
+        eval(&self.expr, secret, None) == Some(true)
+    }
+
+    /// Whether one leaf value of `secret` matches.
+    pub fn matches_leaf(&self, secret: &SecretMetadata, path: &str, value: &str) -> bool {
+        eval(&self.expr, secret, Some((path, value))) == Some(true)
+    }
+}
+
+/// Three-valued evaluation: `None` when the answer depends on a leaf that
+/// was not supplied.
+fn eval(expr: &Expr, secret: &SecretMetadata, leaf: Option<(&str, &str)>) -> Option<bool> {
+    match expr {
+        Expr::Term(term) => eval_term(term, secret, leaf),
+        Expr::Not(inner) => eval(inner, secret, leaf).map(|b| !b),
+        Expr::And(a, b) => match (eval(a, secret, leaf), eval(b, secret, leaf)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Expr::Or(a, b) => match (eval(a, secret, leaf), eval(b, secret, leaf)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+    }
+}
+
+fn eval_term(term: &Term, secret: &SecretMetadata, leaf: Option<(&str, &str)>) -> Option<bool> {
+    match term {
+        Term::Name(p) => Some(p.contained_in(&secret.name)),
+        Term::Description(p) => Some(
+            secret
+                .description
+                .as_deref()
+                .is_some_and(|d| p.contained_in(d)),
+        ),
+        Term::Tag { key, value } => Some(
+            secret
+                .tags
+                .iter()
+                .any(|(k, v)| key.equals(k) && value.as_ref().is_none_or(|value| value.equals(v))),
+        ),
+        Term::Key(p) => leaf.map(|(path, _)| p.contained_in(path)),
+        Term::Value(p) => leaf.map(|(_, value)| p.contained_in(value)),
+    }
+}
+
+/// Records for the secrets that match a query without `key` or `value`
+/// terms. The value is the secret's description.
+pub fn secret_records(query: &Query, secrets: &[SecretMetadata]) -> Vec<KeyValue> {
+    secrets
+        .iter()
+        .filter(|secret| query.matches_secret(secret))
+        .map(|secret| KeyValue {
+            secret: secret.name.clone(),
+            key: None,
+            path: Vec::new(),
+            value: secret.description.clone().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Records for every leaf value that matches, across the fetched secrets.
+pub fn leaf_records(
+    query: &Query,
+    secrets: &[SecretMetadata],
+    data: &BTreeMap<String, BTreeMap<String, Value>>,
+) -> Vec<KeyValue> {
+    let mut records = Vec::new();
+    for secret in secrets {
+        let Some(secret_data) = data.get(&secret.name) else {
+            continue;
+        };
+        walk_leaves(secret_data, &mut |path, value| {
+            let Some(PathSegment::Key(key)) = path.first() else {
+                return;
+            };
+            let value = value_to_string(value);
+            if query.matches_leaf(secret, &key_path(path), &value) {
+                records.push(KeyValue {
+                    secret: secret.name.clone(),
+                    key: Some(key.clone()),
+                    path: path.to_vec(),
+                    value,
+                });
+            }
+        });
+    }
+    records
+}
+
+/// Run `query` against the account: ListSecrets, then GetSecretValue only
+/// for the secrets the metadata terms leave in play, and only when the query
+/// looks at keys or values.
+pub async fn search(
+    client: &Client,
+    query: &Query,
+    decode_embedded: Option<Embedded>,
+) -> Result<Vec<KeyValue>> {
+    let candidates: Vec<SecretMetadata> = list_secret_metadata(client)
+        .await?
+        .into_iter()
+        .filter(|secret| query.may_match(secret))
+        .collect();
+    if !query.needs_values() {
+        return Ok(secret_records(query, &candidates));
+    }
+
+    let names: Vec<String> = candidates.iter().map(|s| s.name.clone()).collect();
+    let mut outcome = fetch_secrets_concurrent(client, &names).await?;
+    for (id, err) in &outcome.failures {
+        eprintln!("warning: skipping secret '{}': {}", id, err);
+    }
+    if let Some(mode) = decode_embedded {
+        decode_all(&mut outcome.secrets, mode);
+    }
+    Ok(leaf_records(query, &candidates, &outcome.secrets))
+}
+
+/// Fail with the usual "no results" error when `records` is empty.
+pub fn require_matches(records: &[KeyValue], query: &str) -> Result<()> {
+    if records.is_empty() {
+        bail!(Error::NoMatches(format!(
+            "No secrets or keys found matching query '{}'",
+            query
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn secret(name: &str, tags: &[(&str, &str)], description: Option<&str>) -> SecretMetadata {
+        SecretMetadata {
+            name: name.to_string(),
+            description: description.map(str::to_string),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn parse_error(query: &str) -> String {
+        Query::parse(query).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_precedence() {
+        let query = Query::parse("name:a OR NOT name:b AND key:c").unwrap();
+        let name = |p: &str| Box::new(Expr::Term(Term::Name(Pattern(p.to_string()))));
+        assert_eq!(
+            query.expr,
+            Expr::Or(
+                name("a"),
+                Box::new(Expr::And(
+                    Box::new(Expr::Not(name("b"))),
+                    Box::new(Expr::Term(Term::Key(Pattern("c".to_string()))))
+                ))
+            )
+        );
+        let quoted = Query::parse(r#"value:"BEGIN \"RSA\" (x)""#).unwrap();
+        assert_eq!(
+            quoted.expr,
+            Expr::Term(Term::Value(Pattern("BEGIN \"RSA\" (x)".to_string())))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors_point_at_the_problem() {
+        assert_eq!(
+            parse_error("key:x AND nme:prod"),
+            "invalid query: unknown field 'nme' (fields: name, key, value, tag, description)\n  \
+             key:x AND nme:prod\n            ^^^"
+        );
+        assert_eq!(
+            parse_error("(name:a OR name:b"),
+            "invalid query: unclosed '('\n  (name:a OR name:b\n  ^"
+        );
+        assert!(parse_error("name:a name:b")
+            .starts_with("invalid query: expected AND, OR or end of query, found a term"));
+        assert!(parse_error("name:a AND").contains("found end of query"));
+        assert!(parse_error("name:a)").contains("unmatched ')'"));
+        assert!(parse_error("key:").contains("missing pattern after 'key:'"));
+        assert!(parse_error("password").contains("expected FIELD:PATTERN, found 'password'"));
+        assert!(parse_error("value:\"open").contains("unclosed '\"'"));
+    }
+
+    #[test]
+    fn test_metadata_terms_decide_before_values() {
+        let query = Query::parse("key:password AND tag:env=prod AND NOT name:legacy/*").unwrap();
+        assert!(query.needs_values());
+        assert!(query.may_match(&secret("prod/db", &[("env", "prod")], None)));
+        assert!(!query.may_match(&secret("legacy/db", &[("env", "prod")], None)));
+        assert!(!query.may_match(&secret("prod/db", &[("env", "production")], None)));
+        assert!(!query.may_match(&secret("prod/db", &[], None)));
+
+        let or = Query::parse("name:prod OR key:password").unwrap();
+        assert!(or.may_match(&secret("dev/db", &[], None)));
+        assert!(or.matches_leaf(&secret("prod/db", &[], None), "user", "x"));
+    }
+
+    #[test]
+    fn test_records() {
+        let secrets = vec![
+            secret("prod/db", &[("env", "prod")], Some("primary database")),
+            secret("prod/api", &[("env", "prod")], None),
+        ];
+        let metadata_only = Query::parse("description:database OR tag:team").unwrap();
+        assert!(!metadata_only.needs_values());
+        let records = secret_records(&metadata_only, &secrets);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].value, "primary database");
+
+        let data: BTreeMap<String, BTreeMap<String, Value>> = [
+            (
+                "prod/db".to_string(),
+                serde_json::from_value(json!({"db": {"password": "p", "user": "u"}})).unwrap(),
+            ),
+            (
+                "prod/api".to_string(),
+                serde_json::from_value(json!({"password": "k"})).unwrap(),
+            ),
+        ]
+        .into();
+        let query = Query::parse("key:password AND NOT value:k").unwrap();
+        let records = leaf_records(&query, &secrets, &data);
+        assert_eq!(records.len(), 1);
+        assert_eq!(key_path(&records[0].path), "db.password");
+    }
+}
@@ -0,0 +1,232 @@
+//! `redact`: a streaming filter that scrubs known secret values.
+//!
+//! Input is processed in chunks so the filter can sit in a CI pipeline. A
+//! value split across two reads is still caught: the tail of each chunk that
+//! could be the start of a value is held back until the next read decides it.
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{Context, Result};
+use std::io::{self, Read, Write};
+
+use crate::commands::{SanitizedComponent, SanitizedPath};
+use crate::scan::{read_chunks, Holdback, Needles};
+
+/// Replaces every needle with `[REDACTED:secret/key.path]`.
+pub struct Redactor {
+    matcher: AhoCorasick,
+    replacements: Vec<String>,
+    holdback: Holdback,
+}
+
+impl Redactor {
+    pub fn new(needles: &Needles) -> Result<Self> {
+        let patterns = needles.patterns();
+        // Leftmost-longest so a value that is a prefix of another value cannot
+        // leave the longer value's remainder in the output.
+        let matcher = AhoCorasick::builder()
+            .match_kind(MatchKind::LeftmostLongest)
+            .build(patterns)
+            .context("Failed to build value matcher")?;
+
+        let replacements = (0..patterns.len())
+            .map(|i| {
+                // Owners are sorted, so the label is stable across runs. The
+                // components are escaped: the label is written into the
+                // stream, and a key name must not be able to forge a log line.
+                let owner = &needles.owners(i)[0];
+                format!(
+                    "[REDACTED:{}/{}]",
+                    SanitizedComponent(&owner.secret),
+                    SanitizedPath(&owner.path)
+                )
+            })
+            .collect();
+
+        Ok(Redactor {
+            matcher,
+            replacements,
+            holdback: needles.holdback(),
+        })
+    }
+
+    /// Copy `input` to `output`, redacting as it goes. Returns the number of
+    /// replacements made.
+    pub fn redact_stream<R: Read, W: Write>(&self, input: R, mut output: W) -> io::Result<u64> {
+        let mut redacted = 0;
+        read_chunks(input, |buf, eof| {
+            let decided = if eof {
+                buf.len()
+            } else {
+                self.holdback.decided_prefix(buf)
+            };
+            let consumed = self.emit(buf, decided, eof, &mut output, &mut redacted)?;
+            output.flush()?;
+            Ok(consumed)
+        })?;
+        Ok(redacted)
+    }
+
+    /// Write out `buf` up to `decided` (extended past it by any match that
+    /// starts before it) and return how many bytes were consumed.
+    fn emit<W: Write>(
+        &self,
+        buf: &[u8],
+        decided: usize,
+        eof: bool,
+        output: &mut W,
+        redacted: &mut u64,
+    ) -> io::Result<usize> {
+        let mut cursor = 0;
+        for m in self.matcher.find_iter(buf) {
+            if m.start() >= decided && !eof {
+                break;
+            }
+            output.write_all(&buf[cursor..m.start()])?;
+            output.write_all(self.replacements[m.pattern().as_usize()].as_bytes())?;
+            cursor = m.end();
+            *redacted += 1;
+        }
+        let end = decided.max(cursor);
+        output.write_all(&buf[cursor..end])?;
+        Ok(end)
+    }
+}
+
+pub fn redact_stdio(redactor: &Redactor) -> Result<u64> {
+    let count = redactor.redact_stream(io::stdin().lock(), io::stdout().lock())?;
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::collections::BTreeMap;
+
+    fn redactor(values: &[(&str, &str, &str)], encoded: bool) -> Redactor {
+        let mut secrets: BTreeMap<String, BTreeMap<String, Value>> = BTreeMap::new();
+        for (secret, key, value) in values {
+            secrets
+                .entry(secret.to_string())
+                .or_default()
+                .insert(key.to_string(), json!(value));
+        }
+        let needles = if encoded {
+            Needles::for_redaction(&secrets)
+        } else {
+            Needles::new(&secrets)
+        };
+        Redactor::new(&needles.unwrap()).unwrap()
+    }
+
+    /// A reader that hands out at most `step` bytes per call, to force values
+    /// across chunk boundaries.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        step: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+            let n = self.step.min(self.data.len()).min(out.len());
+            out[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
+
+    fn run(r: &Redactor, input: &str, step: usize) -> (String, u64) {
+        let mut out = Vec::new();
+        let count = r
+            .redact_stream(
+                Trickle {
+                    data: input.as_bytes(),
+                    step,
+                },
+                &mut out,
+            )
+            .unwrap();
+        (String::from_utf8(out).unwrap(), count)
+    }
+
+    #[test]
+    fn test_values_straddling_reads_are_redacted() {
+        let r = redactor(&[("app", "token", "tok_live_abcdef")], false);
+        let input = "a tok_live_abcdef b\ntok_live_abcdef";
+        for step in 1..=input.len() {
+            let (out, count) = run(&r, input, step);
+            assert_eq!(
+                out, "a [REDACTED:app/token] b\n[REDACTED:app/token]",
+                "step {step}"
+            );
+            assert_eq!(count, 2);
+        }
+    }
+
+    #[test]
+    fn test_longest_value_wins() {
+        let r = redactor(
+            &[
+                ("a", "short", "secret-value"),
+                ("a", "long", "secret-value-extended"),
+            ],
+            false,
+        );
+        for step in [1, 5, 100] {
+            let (out, _) = run(&r, "x secret-value-extended y", step);
+            assert_eq!(out, "x [REDACTED:a/long] y", "step {step}");
+        }
+    }
+
+    #[test]
+    fn test_encoded_forms_are_redacted() {
+        let r = redactor(&[("db", "password", "p@ss word!")], true);
+        let (out, count) = run(&r, "url=p%40ss%20word%21 b64=cEBzcyB3b3JkIQ==\n", 3);
+        assert_eq!(
+            out,
+            "url=[REDACTED:db/password] b64=[REDACTED:db/password]\n"
+        );
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_label_cannot_forge_a_line() {
+        let r = redactor(&[("app", "evil\nkey", "tok_live_abcdef")], false);
+        let (out, _) = run(&r, "tok_live_abcdef", 4);
+        assert_eq!(out, "[REDACTED:app/evil\\x0akey]");
+    }
+
+    #[test]
+    fn test_nested_values_are_redacted() {
+        let mut data = BTreeMap::new();
+        data.insert(
+            "db".to_string(),
+            json!({"password": "hunter2hunter2", "replicas": [{"token": "tok_live_abcdef"}]}),
+        );
+        let mut secrets = BTreeMap::new();
+        secrets.insert("app".to_string(), data);
+        let r = Redactor::new(&Needles::new(&secrets).unwrap()).unwrap();
+
+        let (out, count) = run(&r, "pw=hunter2hunter2 t=tok_live_abcdef\n", 3);
+        assert_eq!(
+            out,
+            "pw=[REDACTED:app/db.password] t=[REDACTED:app/db.replicas[0].token]\n"
+        );
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn test_short_values_are_redacted() {
+        let r = redactor(&[("app", "pin", "4711"), ("app", "flag", "")], true);
+        let (out, count) = run(&r, "pin=4711 flag=\n", 2);
+        assert_eq!(out, "pin=[REDACTED:app/pin] flag=\n");
+        assert_eq!(count, 1);
+    }
+
+    #[test]
+    fn test_unmatched_input_passes_through_unchanged() {
+        let r = redactor(&[("app", "token", "tok_live_abcdef")], false);
+        let input = "nothing to see\ntok_live_abcde\n";
+        assert_eq!(run(&r, input, 2), (input.to_string(), 0));
+    }
+}
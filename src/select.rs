@@ -0,0 +1,105 @@
+//! Secret selection by name or glob pattern.
+//!
+//! Commands that act on "some secrets" (`scan`, `redact`, ...) take one or more
+//! `--secret` arguments. A literal name is used as-is, so it can be fetched
+//! without `ListSecrets`; a pattern containing `*` or `?` is matched against
+//! the account's secret names.
+
+/// Whether `pattern` contains glob metacharacters.
+pub fn is_pattern(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+/// Match `text` against a glob where `*` matches any run of characters
+/// (including `/`) and `?` matches exactly one character.
+///
+/// Iterative with single-star backtracking, so it runs in O(pattern * text)
+/// even on adversarial secret names.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((star_pi, star_ti)) = star {
+            pi = star_pi + 1;
+            ti = star_ti + 1;
+            star = Some((star_pi, star_ti + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
+/// Resolve `patterns` against `names`, preserving the order of `names` and
+/// dropping duplicates. Literal patterns are passed through even when absent
+/// from `names`, so the subsequent fetch reports them as not found.
+pub fn select(names: &[String], patterns: &[String]) -> Vec<String> {
+    let mut selected: Vec<String> = names
+        .iter()
+        .filter(|name| {
+            patterns.iter().any(|p| {
+                if is_pattern(p) {
+                    glob_match(p, name)
+                } else {
+                    p == *name
+                }
+            })
+        })
+        .cloned()
+        .collect();
+    for literal in patterns.iter().filter(|p| !is_pattern(p)) {
+        if !selected.contains(literal) {
+            selected.push(literal.clone());
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("app/*", "app/db"));
+        assert!(glob_match("app/*", "app/db/nested"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*-prod-*", "api-prod-key"));
+        assert!(!glob_match("app/*", "other/app/db"));
+        assert!(!glob_match("a?c", "ac"));
+        assert!(!glob_match("exact", "exactly"));
+    }
+
+    #[test]
+    fn test_glob_match_is_not_exponential() {
+        let text = "a".repeat(5_000);
+        assert!(!glob_match(&format!("{}b", "*a".repeat(50)), &text));
+    }
+
+    #[test]
+    fn test_select_mixes_literals_and_patterns() {
+        let names: Vec<String> = ["app/db", "app/api", "ops/db"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let patterns = vec![
+            "app/*".to_string(),
+            "ops/db".to_string(),
+            "gone".to_string(),
+        ];
+        assert_eq!(
+            select(&names, &patterns),
+            ["app/db", "app/api", "ops/db", "gone"]
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! `serve`: a localhost endpoint compatible with the AWS Secrets Manager
+//! Agent.
+//!
+//! `GET /secretsmanager/get?secretId=...` (with optional `versionId` /
+//! `versionStage`) returns the `GetSecretValue` JSON the agent returns. Every
+//! request must carry the `X-Aws-Parameters-Secrets-Token` header matching the
+//! configured token, and requests bearing `X-Forwarded-For` are refused, so a
+//! server-side request forgery in a co-located application cannot read
+//! secrets through this endpoint. Responses are cached in memory for a TTL.
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_secretsmanager::error::ProvideErrorMetadata;
+use aws_sdk_secretsmanager::operation::get_secret_value::GetSecretValueError;
+use aws_sdk_secretsmanager::{error::SdkError, Client};
+use futures::future::BoxFuture;
+use http_body_util::Full;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::rt::{TokioIo, TokioTimer};
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+use crate::aws::{fetch_secret_value, SecretValue};
+
+/// Header carrying the SSRF token, as with the agent.
+pub const TOKEN_HEADER: &str = "x-aws-parameters-secrets-token";
+
+/// Environment variable the token is read from when `--token-file` is not
+/// given; `file://<path>` reads it from a file, as with the agent.
+pub const TOKEN_ENV: &str = "AWS_TOKEN";
+
+/// Maximum cached responses. Bounds memory when callers ask for many ids.
+const MAX_CACHE_ENTRIES: usize = 1_000;
+
+/// Time allowed for a client to send its request headers.
+const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a cached request names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SecretRequest {
+    pub secret_id: String,
+    pub version_id: Option<String>,
+    pub version_stage: Option<String>,
+}
+
+/// Fetches one secret version; [`fetch_secret_value`] in production.
+pub type Fetch =
+    Arc<dyn Fn(SecretRequest) -> BoxFuture<'static, Result<SecretValue>> + Send + Sync>;
+
+/// Serialized responses keyed by request, each valid until its deadline.
+pub struct SecretCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<SecretRequest, (Instant, Arc<String>)>>,
+}
+
+impl SecretCache {
+    pub fn new(ttl: Duration) -> Self {
+        SecretCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn get(&self, request: &SecretRequest) -> Option<Arc<String>> {
+        let entries = self.entries.lock().unwrap();
+        let (deadline, body) = entries.get(request)?;
+        (Instant::now() < *deadline).then(|| Arc::clone(body))
+    }
+
+    fn insert(&self, request: SecretRequest, body: Arc<String>) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_CACHE_ENTRIES {
+            entries.retain(|_, (deadline, _)| now < *deadline);
+        }
+        if entries.len() >= MAX_CACHE_ENTRIES {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (deadline, _))| *deadline)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(request, (now + self.ttl, body));
+    }
+}
+
+pub struct Server {
+    token: String,
+    cache: SecretCache,
+    fetch: Fetch,
+}
+
+impl Server {
+    pub fn new(token: String, ttl: Duration, fetch: Fetch) -> Self {
+        Server {
+            token,
+            cache: SecretCache::new(ttl),
+            fetch,
+        }
+    }
+
+    /// A server that fetches through `client`.
+    pub fn with_client(token: String, ttl: Duration, client: Client) -> Self {
+        let fetch: Fetch = Arc::new(move |request: SecretRequest| {
+            let client = client.clone();
+            Box::pin(async move {
+                fetch_secret_value(
+                    &client,
+                    &request.secret_id,
+                    request.version_id.as_deref(),
+                    request.version_stage.as_deref(),
+                )
+                .await
+            })
+        });
+        Server::new(token, ttl, fetch)
+    }
+
+    /// Answer one request.
+    pub async fn respond(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        headers: &HeaderMap,
+    ) -> Response<Full<Bytes>> {
+        if headers.contains_key("x-forwarded-for") {
+            return error(
+                StatusCode::BAD_REQUEST,
+                "Forwarded requests are not allowed",
+            );
+        }
+        let authorized = headers
+            .get(TOKEN_HEADER)
+            .is_some_and(|value| constant_time_eq(value.as_bytes(), self.token.as_bytes()));
+        if !authorized {
+            return error(
+                StatusCode::FORBIDDEN,
+                "Bad or missing X-Aws-Parameters-Secrets-Token header",
+            );
+        }
+        if method != Method::GET {
+            return error(StatusCode::METHOD_NOT_ALLOWED, "Only GET is supported");
+        }
+
+        match uri.path() {
+            "/ping" => text(StatusCode::OK, "healthy".to_string()),
+            "/secretsmanager/get" => match parse_query(uri.query().unwrap_or("")) {
+                Ok(request) => self.get_secret(request).await,
+                Err(err) => error(StatusCode::BAD_REQUEST, &err.to_string()),
+            },
+            _ => error(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+
+    async fn get_secret(&self, request: SecretRequest) -> Response<Full<Bytes>> {
+        if let Some(body) = self.cache.get(&request) {
+            return json_body(StatusCode::OK, body.to_string());
+        }
+        match (self.fetch)(request.clone()).await {
+            Ok(value) => match serde_json::to_string(&value) {
+                Ok(body) => {
+                    let body = Arc::new(body);
+                    self.cache.insert(request, Arc::clone(&body));
+                    json_body(StatusCode::OK, body.to_string())
+                }
+                Err(err) => error(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+            },
+            Err(err) => fetch_error(&request.secret_id, &err),
+        }
+    }
+}
+
+/// Parse `secretId`, `versionId` and `versionStage` from a query string.
+/// `+` decodes to a space, as in HTML forms and the agent.
+fn parse_query(query: &str) -> Result<SecretRequest> {
+    let mut params: HashMap<String, String> = HashMap::new();
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let decode = |s: &str| -> Result<String> {
+            Ok(percent_decode_str(&s.replace('+', " "))
+                .decode_utf8()
+                .context("query string is not valid UTF-8")?
+                .into_owned())
+        };
+        params.insert(decode(name)?, decode(value)?);
+    }
+    let Some(secret_id) = params.remove("secretId").filter(|id| !id.is_empty()) else {
+        bail!("missing secretId query parameter");
+    };
+    Ok(SecretRequest {
+        secret_id,
+        version_id: params.remove("versionId"),
+        version_stage: params.remove("versionStage"),
+    })
+}
+
+/// The agent's error shape for a failed fetch: AWS service errors keep their
+/// error code with status 400, everything else is a 500.
+fn fetch_error(secret_id: &str, err: &anyhow::Error) -> Response<Full<Bytes>> {
+    let service_code = err.chain().find_map(|cause| {
+        cause
+            .downcast_ref::<SdkError<GetSecretValueError>>()
+            .and_then(SdkError::as_service_error)
+            .and_then(|service| service.code().map(str::to_string))
+    });
+    eprintln!(
+        "warning: failed to fetch secret '{}': {:#}",
+        secret_id.escape_debug(),
+        err
+    );
+    match service_code {
+        Some(code) => json_body(
+            StatusCode::BAD_REQUEST,
+            json!({ "__type": code, "message": format!("{err:#}") }).to_string(),
+        ),
+        None => error(StatusCode::INTERNAL_SERVER_ERROR, &format!("{err:#}")),
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn json_body(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    response(status, "application/json", body)
+}
+
+fn text(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    response(status, "text/plain", body)
+}
+
+fn error(status: StatusCode, message: &str) -> Response<Full<Bytes>> {
+    text(status, format!("{message}\n"))
+}
+
+fn response(status: StatusCode, content_type: &str, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    if let Ok(value) = content_type.parse() {
+        response.headers_mut().insert(CONTENT_TYPE, value);
+    }
+    response
+}
+
+/// Resolve the SSRF token from `--token-file` or [`TOKEN_ENV`].
+pub fn load_token(token_file: Option<&std::path::Path>) -> Result<String> {
+    let token = match (token_file, std::env::var(TOKEN_ENV).ok()) {
+        (Some(path), _) => read_token_file(path)?,
+        (None, Some(value)) => match value.strip_prefix("file://") {
+            Some(path) => read_token_file(path.as_ref())?,
+            None => value,
+        },
+        (None, None) => bail!("No token configured: pass --token-file or set {TOKEN_ENV}"),
+    };
+    let token = token.trim().to_string();
+    if token.is_empty() {
+        bail!("The configured token is empty");
+    }
+    Ok(token)
+}
+
+fn read_token_file(path: &std::path::Path) -> Result<String> {
+    std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read token file '{}'", path.display()))
+}
+
+/// Accept connections on `addr` until the process is stopped.
+pub async fn run(server: Server, addr: SocketAddr) -> Result<()> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to listen on {addr}"))?;
+    eprintln!("listening on http://{}", listener.local_addr()?);
+
+    let server = Arc::new(server);
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(conn) => conn,
+            Err(err) => {
+                eprintln!("warning: failed to accept connection: {err}");
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        tokio::spawn(async move {
+            let service = service_fn(move |request: Request<hyper::body::Incoming>| {
+                let server = Arc::clone(&server);
+                async move {
+                    Ok::<_, std::convert::Infallible>(
+                        server
+                            .respond(request.method(), request.uri(), request.headers())
+                            .await,
+                    )
+                }
+            });
+            let _ = http1::Builder::new()
+                .timer(TokioTimer::new())
+                .header_read_timeout(HEADER_READ_TIMEOUT)
+                .serve_connection(TokioIo::new(stream), service)
+                .await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http_body_util::BodyExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const TOKEN: &str = "s3cr3t-token";
+
+    fn server(ttl: Duration) -> (Server, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let fetch: Fetch = Arc::new(move |request: SecretRequest| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if request.secret_id == "missing" {
+                    bail!("no such secret");
+                }
+                Ok(SecretValue {
+                    arn: Some(format!(
+                        "arn:aws:secretsmanager:::secret:{}",
+                        request.secret_id
+                    )),
+                    name: Some(request.secret_id),
+                    version_id: request.version_id,
+                    secret_string: Some(r#"{"k":"v"}"#.to_string()),
+                    secret_binary: None,
+                    version_stages: vec!["AWSCURRENT".to_string()],
+                    created_date: Some(1_700_000_000.5),
+                })
+            })
+        });
+        (Server::new(TOKEN.to_string(), ttl, fetch), calls)
+    }
+
+    async fn get(server: &Server, uri: &str, headers: &[(&str, &str)]) -> (StatusCode, String) {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.insert(
+                hyper::header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        let response = server
+            .respond(&Method::GET, &uri.parse().unwrap(), &map)
+            .await;
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_agent_response_shape_and_cache() {
+        let (server, calls) = server(Duration::from_secs(300));
+        let uri = "/secretsmanager/get?secretId=prod%2Fapp%3Fx";
+        let (status, body) = get(&server, uri, &[(TOKEN_HEADER, TOKEN)]).await;
+        assert_eq!(status, StatusCode::OK);
+        let json: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(json["Name"], "prod/app?x");
+        assert_eq!(json["SecretString"], r#"{"k":"v"}"#);
+        assert_eq!(json["VersionStages"][0], "AWSCURRENT");
+        assert!(json.get("SecretBinary").is_none());
+
+        get(&server, uri, &[(TOKEN_HEADER, TOKEN)]).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        get(
+            &server,
+            &format!("{uri}&versionStage=AWSPREVIOUS"),
+            &[(TOKEN_HEADER, TOKEN)],
+        )
+        .await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_disables_cache() {
+        let (server, calls) = server(Duration::ZERO);
+        for _ in 0..2 {
+            get(
+                &server,
+                "/secretsmanager/get?secretId=a",
+                &[(TOKEN_HEADER, TOKEN)],
+            )
+            .await;
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_missing_token_and_forwarded_requests() {
+        let (server, calls) = server(Duration::from_secs(300));
+        let uri = "/secretsmanager/get?secretId=a";
+        assert_eq!(get(&server, uri, &[]).await.0, StatusCode::FORBIDDEN);
+        assert_eq!(
+            get(&server, uri, &[(TOKEN_HEADER, "s3cr3t-tokeX")]).await.0,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            get(
+                &server,
+                uri,
+                &[(TOKEN_HEADER, TOKEN), ("x-forwarded-for", "10.0.0.1")]
+            )
+            .await
+            .0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_bad_requests_and_fetch_failures() {
+        let (server, calls) = server(Duration::from_secs(300));
+        let auth = [(TOKEN_HEADER, TOKEN)];
+        assert_eq!(
+            get(&server, "/secretsmanager/get", &auth).await.0,
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(get(&server, "/other", &auth).await.0, StatusCode::NOT_FOUND);
+        assert_eq!(
+            get(&server, "/ping", &auth).await,
+            (StatusCode::OK, "healthy".to_string())
+        );
+
+        let uri = "/secretsmanager/get?secretId=missing";
+        assert_eq!(
+            get(&server, uri, &auth).await.0,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        get(&server, uri, &auth).await;
+        assert_eq!(calls.load(Ordering::SeqCst), 2, "failures are not cached");
+    }
+
+    #[test]
+    fn test_parse_query() {
+        let request = parse_query("versionId=v1&secretId=a+b%2Bc").unwrap();
+        assert_eq!(request.secret_id, "a b+c");
+        assert_eq!(request.version_id.as_deref(), Some("v1"));
+        assert!(parse_query("secretId=").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! `tf-external`: Terraform's `external` data source protocol.
+//!
+//! Terraform writes a JSON object of strings to stdin and expects a flat JSON
+//! object of strings on stdout. On failure the program must exit non-zero with
+//! the reason on stderr, which Terraform shows as the data source's error.
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+use crate::commands::value_to_string;
+
+/// The `query` block of an `external` data source.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Query {
+    pub secret: String,
+    /// Comma-separated keys to return; every key when absent or empty.
+    /// (Terraform only passes strings, so this cannot be a list.)
+    #[serde(default)]
+    pub keys: Option<String>,
+}
+
+impl Query {
+    pub fn parse<R: Read>(mut input: R) -> Result<Self> {
+        let mut text = String::new();
+        input
+            .read_to_string(&mut text)
+            .context("Failed to read query from stdin")?;
+        let query: Query = serde_json::from_str(&text)
+            .context("Query must be a JSON object with \"secret\" and optional \"keys\"")?;
+        if query.secret.is_empty() {
+            bail!("Query \"secret\" must not be empty");
+        }
+        Ok(query)
+    }
+
+    fn keys(&self) -> Option<Vec<&str>> {
+        let keys: Vec<&str> = self
+            .keys
+            .as_deref()?
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .collect();
+        (!keys.is_empty()).then_some(keys)
+    }
+}
+
+/// The flat string map Terraform expects: the requested keys (or all of
+/// them), with nested values rendered by [`value_to_string`]. Every missing
+/// key is reported at once.
+pub fn result_map(
+    query: &Query,
+    data: &BTreeMap<String, Value>,
+) -> Result<BTreeMap<String, String>> {
+    let Some(keys) = query.keys() else {
+        return Ok(data
+            .iter()
+            .map(|(key, value)| (key.clone(), value_to_string(value)))
+            .collect());
+    };
+
+    let missing: Vec<&str> = keys
+        .iter()
+        .copied()
+        .filter(|key| !data.contains_key(*key))
+        .collect();
+    if !missing.is_empty() {
+        bail!(
+            "Secret '{}' has no key(s): {}",
+            query.secret,
+            missing.join(", ")
+        );
+    }
+    Ok(keys
+        .into_iter()
+        .map(|key| (key.to_string(), value_to_string(&data[key])))
+        .collect())
+}
+
+pub fn write_result<W: Write>(w: &mut W, result: &BTreeMap<String, String>) -> Result<()> {
+    writeln!(w, "{}", serde_json::to_string(result)?)?;
+    Ok(())
+}
+
+pub fn show_result(result: &BTreeMap<String, String>) -> Result<()> {
+    write_result(&mut io::stdout().lock(), result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn data() -> BTreeMap<String, Value> {
+        [
+            ("password", json!("hunter2")),
+            ("port", json!(5432)),
+            ("replicas", json!(["a", "b"])),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect()
+    }
+
+    #[test]
+    fn test_all_keys_are_flattened_to_strings() {
+        let query = Query::parse(r#"{"secret": "prod/db"}"#.as_bytes()).unwrap();
+        let mut out = Vec::new();
+        write_result(&mut out, &result_map(&query, &data()).unwrap()).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            r#"{"password":"hunter2","port":"5432","replicas":"[\"a\",\"b\"]"}"#.to_string() + "\n"
+        );
+    }
+
+    #[test]
+    fn test_selected_keys() {
+        let query =
+            Query::parse(r#"{"secret": "prod/db", "keys": "port, password"}"#.as_bytes()).unwrap();
+        let result = result_map(&query, &data()).unwrap();
+        assert_eq!(result.keys().collect::<Vec<_>>(), ["password", "port"]);
+
+        let query =
+            Query::parse(r#"{"secret": "prod/db", "keys": "port,user,host"}"#.as_bytes()).unwrap();
+        let err = result_map(&query, &data()).unwrap_err().to_string();
+        assert!(err.ends_with("has no key(s): user, host"), "{err}");
+    }
+
+    #[test]
+    fn test_rejects_malformed_queries() {
+        assert!(Query::parse(r#"{"secret": ""}"#.as_bytes()).is_err());
+        assert!(Query::parse(r#"{"secrte": "typo"}"#.as_bytes()).is_err());
+        assert!(Query::parse(r#"{"secret": "a", "extra": "x"}"#.as_bytes()).is_err());
+        assert!(Query::parse("not json".as_bytes()).is_err());
+    }
+}
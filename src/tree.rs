@@ -0,0 +1,217 @@
+//! `tree` / `list --tree`: secret names as a hierarchy split on `/`.
+//!
+//! Each segment is drawn with [`SanitizedComponent`], so a segment can hold
+//! neither a line break nor anything that passes for a `/`; the drawn tree
+//! maps back to exactly one set of names.
+
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::cli::OutputFormat;
+use crate::commands::{Sanitized, SanitizedComponent};
+
+/// One segment of the hierarchy.
+#[derive(Debug, Default, Serialize)]
+pub struct Node {
+    pub name: String,
+    /// Whether a secret is named by the path to this node.
+    pub secret: bool,
+    /// Secrets below this node, including those cut off by `--depth`.
+    pub count: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<Node>,
+}
+
+#[derive(Default)]
+struct Builder {
+    secret: bool,
+    count: usize,
+    children: BTreeMap<String, Builder>,
+}
+
+impl Builder {
+    fn insert(&mut self, segments: &[&str], depth: Option<usize>) {
+        let Some((first, rest)) = segments.split_first() else {
+            self.secret = true;
+            return;
+        };
+        self.count += 1;
+        if depth == Some(0) {
+            return;
+        }
+        self.children
+            .entry(first.to_string())
+            .or_default()
+            .insert(rest, depth.map(|d| d - 1));
+    }
+
+    fn finish(self, name: String) -> Node {
+        Node {
+            name,
+            secret: self.secret,
+            count: self.count,
+            children: self
+                .children
+                .into_iter()
+                .map(|(name, child)| child.finish(name))
+                .collect(),
+        }
+    }
+}
+
+/// Build the hierarchy of `names` below `root` (a `/`-separated prefix, or
+/// everything when `None`), at most `depth` levels deep.
+pub fn build(names: &[String], root: Option<&str>, depth: Option<usize>) -> Node {
+    let root = root
+        .map(|r| r.trim_end_matches('/'))
+        .filter(|r| !r.is_empty());
+    let mut builder = Builder::default();
+    for name in names {
+        let rest = match root {
+            None => name.as_str(),
+            Some(root) if name == root => {
+                builder.secret = true;
+                continue;
+            }
+            Some(root) => match name
+                .strip_prefix(root)
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => continue,
+            },
+        };
+        let segments: Vec<&str> = rest.split('/').collect();
+        builder.insert(&segments, depth);
+    }
+    builder.finish(root.unwrap_or_default().to_string())
+}
+
+pub fn show_tree(tree: &Node, format: OutputFormat) -> Result<()> {
+    write_tree(&mut io::stdout().lock(), tree, format)
+}
+
+pub fn write_tree<W: Write>(w: &mut W, tree: &Node, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => {
+            writeln!(w, "{}", serde_json::to_string_pretty(tree)?)?;
+        }
+        OutputFormat::Plain => {
+            if tree.name.is_empty() {
+                writeln!(w, ". ({})", tree.count)?;
+            } else {
+                writeln!(w, "{}/ ({})", Sanitized(&tree.name), tree.count)?;
+            }
+            write_children(w, &tree.children, "")?;
+        }
+    }
+    Ok(())
+}
+
+/// Draw `nodes` below a parent whose lines start with `indent`. A node that
+/// is both a secret and a branch is drawn twice: once as the secret, once as
+/// the branch (`name/`) with its count.
+fn write_children<W: Write>(w: &mut W, nodes: &[Node], indent: &str) -> io::Result<()> {
+    let lines: Vec<(&Node, bool)> = nodes
+        .iter()
+        .flat_map(|node| {
+            let leaf = node.secret.then_some((node, false));
+            let branch = (node.count > 0).then_some((node, true));
+            leaf.into_iter().chain(branch)
+        })
+        .collect();
+    for (i, (node, branch)) in lines.iter().enumerate() {
+        let last = i + 1 == lines.len();
+        let connector = if last { "└── " } else { "├── " };
+        let name = SanitizedComponent(&node.name);
+        if *branch {
+            writeln!(w, "{indent}{connector}{name}/ ({})", node.count)?;
+            let indent = format!("{indent}{}", if last { "    " } else { "│   " });
+            write_children(w, &node.children, &indent)?;
+        } else {
+            writeln!(w, "{indent}{connector}{name}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names() -> Vec<String> {
+        [
+            "prod/app/db",
+            "prod/app/db/replica",
+            "prod/app/cache",
+            "prod/web",
+            "dev/app/db",
+            "standalone",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    fn plain(tree: &Node) -> String {
+        let mut out = Vec::new();
+        write_tree(&mut out, tree, OutputFormat::Plain).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_plain_tree_with_counts() {
+        assert_eq!(
+            plain(&build(&names(), None, None)),
+            ". (6)\n\
+             ├── dev/ (1)\n\
+             │   └── app/ (1)\n\
+             │       └── db\n\
+             ├── prod/ (4)\n\
+             │   ├── app/ (3)\n\
+             │   │   ├── cache\n\
+             │   │   ├── db\n\
+             │   │   └── db/ (1)\n\
+             │   │       └── replica\n\
+             │   └── web\n\
+             └── standalone\n"
+        );
+    }
+
+    #[test]
+    fn test_root_and_depth() {
+        assert_eq!(
+            plain(&build(&names(), Some("prod/"), Some(1))),
+            "prod/ (4)\n├── app/ (3)\n└── web\n"
+        );
+        let tree = build(&names(), Some("prod/app/db"), None);
+        assert!(tree.secret);
+        assert_eq!(tree.count, 1);
+        assert!(build(&names(), Some("pro"), None).children.is_empty());
+    }
+
+    #[test]
+    fn test_json_nesting() {
+        let tree = build(&names(), Some("dev"), None);
+        let json = serde_json::to_value(&tree).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "name": "dev", "secret": false, "count": 1,
+                "children": [{
+                    "name": "app", "secret": false, "count": 1,
+                    "children": [{"name": "db", "secret": true, "count": 0}]
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_segments_are_sanitized() {
+        let tree = build(&["a/evil\n└── forged".to_string()], None, None);
+        let out = plain(&tree);
+        assert_eq!(out.lines().count(), 3);
+        assert!(out.contains("evil\\x0a"));
+    }
+}
@@ -0,0 +1,449 @@
+//! `watch`: poll secrets for changes, re-render outputs and run a hook.
+//!
+//! Each poll is one DescribeSecret per secret; the value is only fetched again
+//! when the `AWSCURRENT` version id or `LastChangedDate` moves. Every change
+//! is reported as one event line on stdout; the hook's own output goes to
+//! stderr so the event stream stays machine-readable.
+
+use anyhow::{bail, Context, Result};
+use aws_sdk_secretsmanager::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::os::fd::AsFd;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::aws::{
+    describe_secrets_concurrent, fetch_all_with, fetch_secret_value, parse_secret_object,
+    FetchOutcome, SecretMetadata,
+};
+use crate::cli::OutputFormat;
+use crate::commands::Sanitized;
+use crate::inspect::format_timestamp;
+use crate::outputs::Output;
+
+/// Environment variable listing the changed secrets (one per line) for the
+/// hook.
+pub const CHANGED_ENV: &str = "GOLDFINCH_CHANGED_SECRETS";
+
+/// What identifies one version of a secret's value.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Fingerprint {
+    pub version_id: Option<String>,
+    pub last_changed: Option<i64>,
+}
+
+impl From<&SecretMetadata> for Fingerprint {
+    fn from(metadata: &SecretMetadata) -> Self {
+        Fingerprint {
+            version_id: metadata.current_version.clone(),
+            last_changed: metadata.last_changed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChangeEvent {
+    pub event: &'static str,
+    pub secret: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_changed: Option<String>,
+    /// The hook's exit code; `None` without a hook, when it was killed by a
+    /// signal, or when it was skipped because an output failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hook_exit_code: Option<i32>,
+    /// Whether any output could not be updated. The hook is not run then, so
+    /// it never reloads against a stale or missing file.
+    pub outputs_failed: bool,
+}
+
+/// The watched secrets' last seen fingerprints and values.
+pub struct WatchState {
+    /// In command-line order: later secrets win when keys are merged.
+    names: Vec<String>,
+    fingerprints: BTreeMap<String, Fingerprint>,
+    data: BTreeMap<String, BTreeMap<String, Value>>,
+}
+
+impl WatchState {
+    pub fn new(names: Vec<String>) -> Self {
+        WatchState {
+            names,
+            fingerprints: BTreeMap::new(),
+            data: BTreeMap::new(),
+        }
+    }
+
+    /// Secrets whose fingerprint differs from the one last recorded.
+    pub fn changed(&self, current: &BTreeMap<String, SecretMetadata>) -> Vec<String> {
+        self.names
+            .iter()
+            .filter(|name| {
+                current.get(*name).is_some_and(|metadata| {
+                    self.fingerprints.get(*name) != Some(&Fingerprint::from(metadata))
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn record(&mut self, name: &str, fingerprint: Fingerprint, data: BTreeMap<String, Value>) {
+        self.fingerprints.insert(name.to_string(), fingerprint);
+        self.data.insert(name.to_string(), data);
+    }
+
+    /// Record a value fetched as `version_id`, returning whether that version
+    /// is new.
+    ///
+    /// The version comes from GetSecretValue, not from `metadata`: the
+    /// describe ran first, so a rotation in between would pair the new value
+    /// with the old version id. `last_changed` is kept only when both calls
+    /// saw the same version; otherwise the next poll fetches again, and finds
+    /// the version already recorded.
+    pub fn record_fetched(
+        &mut self,
+        name: &str,
+        metadata: Option<&SecretMetadata>,
+        version_id: Option<String>,
+        data: BTreeMap<String, Value>,
+    ) -> bool {
+        let seen = version_id.is_some()
+            && self
+                .fingerprints
+                .get(name)
+                .is_some_and(|recorded| recorded.version_id == version_id);
+        let last_changed = metadata
+            .filter(|metadata| metadata.current_version == version_id)
+            .and_then(|metadata| metadata.last_changed);
+        let fingerprint = Fingerprint {
+            version_id,
+            last_changed,
+        };
+        self.record(name, fingerprint, data);
+        !seen
+    }
+
+    /// Every watched secret's keys in one map, later secrets overriding
+    /// earlier ones.
+    pub fn merged(&self) -> BTreeMap<String, Value> {
+        let mut merged = BTreeMap::new();
+        for name in &self.names {
+            if let Some(data) = self.data.get(name) {
+                merged.extend(data.iter().map(|(k, v)| (k.clone(), v.clone())));
+            }
+        }
+        merged
+    }
+}
+
+/// Fetch the changed secrets and record them. Returns the events for those
+/// that could be read and hold a version not seen before; the rest keep
+/// their old fingerprint and are retried on the next poll.
+async fn refresh(
+    client: &Client,
+    state: &mut WatchState,
+    changed: &[String],
+    metadata: &BTreeMap<String, SecretMetadata>,
+) -> Result<Vec<ChangeEvent>> {
+    let outcome = fetch_versions(client, changed).await?;
+    for (id, err) in &outcome.failures {
+        eprintln!("warning: skipping secret '{}': {}", id, err);
+    }
+    let mut events = Vec::new();
+    for (name, (version_id, data)) in outcome.secrets {
+        if !state.record_fetched(&name, metadata.get(&name), version_id, data) {
+            continue;
+        }
+        let fingerprint = &state.fingerprints[&name];
+        events.push(ChangeEvent {
+            event: "changed",
+            version_id: fingerprint.version_id.clone(),
+            last_changed: fingerprint.last_changed.map(format_timestamp),
+            secret: name,
+            hook_exit_code: None,
+            outputs_failed: false,
+        });
+    }
+    Ok(events)
+}
+
+/// Fetch each secret's current value with the version id it was read as.
+async fn fetch_versions(
+    client: &Client,
+    names: &[String],
+) -> Result<FetchOutcome<(Option<String>, BTreeMap<String, Value>)>> {
+    fetch_all_with(names, |id| async move {
+        let value = fetch_secret_value(client, &id, None, None).await?;
+        let secret_string = value
+            .secret_string
+            .context("Secret does not contain a string value")?;
+        Ok((value.version_id, parse_secret_object(&secret_string)?))
+    })
+    .await
+}
+
+async fn describe(client: &Client, names: &[String]) -> Result<BTreeMap<String, SecretMetadata>> {
+    let outcome = describe_secrets_concurrent(client, names).await?;
+    for (id, err) in &outcome.failures {
+        eprintln!("warning: could not check secret '{}': {}", id, err);
+    }
+    Ok(outcome.secrets)
+}
+
+/// Run `sh -c hook` with the changed secrets in [`CHANGED_ENV`] and its
+/// stdout sent to our stderr.
+async fn run_hook(hook: &str, changed: &[String]) -> Result<Option<i32>> {
+    let stdout = io::stderr().as_fd().try_clone_to_owned()?;
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(hook)
+        .env(CHANGED_ENV, changed.join("\n"))
+        .stdin(Stdio::null())
+        .stdout(Stdio::from(stdout));
+    let status = tokio::task::spawn_blocking(move || command.status())
+        .await?
+        .context("Failed to run hook")?;
+    Ok(status.code())
+}
+
+fn write_event<W: Write>(w: &mut W, event: &ChangeEvent, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Json => writeln!(w, "{}", serde_json::to_string(event)?)?,
+        OutputFormat::Plain => {
+            write!(w, "{} secret={}", event.event, Sanitized(&event.secret))?;
+            if let Some(version_id) = &event.version_id {
+                write!(w, " version_id={}", Sanitized(version_id))?;
+            }
+            if let Some(last_changed) = &event.last_changed {
+                write!(w, " last_changed={last_changed}")?;
+            }
+            if let Some(code) = event.hook_exit_code {
+                write!(w, " hook_exit_code={code}")?;
+            }
+            if event.outputs_failed {
+                write!(w, " outputs_failed=true")?;
+            }
+            writeln!(w)?;
+        }
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// Re-render every output from `merged`, then run the hook for `events`.
+/// If any output fails, the hook is skipped, so it never reloads against a
+/// stale or missing file, and the events say so.
+async fn publish(
+    outputs: &[Output],
+    merged: &BTreeMap<String, Value>,
+    hook: Option<&str>,
+    events: &mut [ChangeEvent],
+) {
+    let mut outputs_failed = false;
+    for output in outputs {
+        if let Err(err) = output.write(merged) {
+            eprintln!(
+                "warning: failed to update '{}': {:#}",
+                output.path().display(),
+                err
+            );
+            outputs_failed = true;
+        }
+    }
+    if outputs_failed {
+        events.iter_mut().for_each(|e| e.outputs_failed = true);
+        if hook.is_some() {
+            eprintln!("warning: not running the hook because an output failed");
+        }
+    } else if let Some(hook) = hook {
+        let updated: Vec<String> = events.iter().map(|e| e.secret.clone()).collect();
+        match run_hook(hook, &updated).await {
+            Ok(code) => events.iter_mut().for_each(|e| e.hook_exit_code = code),
+            Err(err) => eprintln!("warning: {:#}", err),
+        }
+    }
+}
+
+/// Watch `names` until the process is stopped.
+pub async fn watch(
+    client: &Client,
+    names: Vec<String>,
+    interval: Duration,
+    outputs: &[Output],
+    hook: Option<&str>,
+    format: OutputFormat,
+) -> Result<()> {
+    if interval.is_zero() {
+        bail!("--interval must be greater than zero");
+    }
+    let mut state = WatchState::new(names.clone());
+
+    // The initial read must succeed in full: outputs rendered from a partial
+    // set would silently lack keys.
+    let metadata = describe(client, &names).await?;
+    let initial = fetch_versions(client, &names).await?;
+    if let Some((id, err)) = initial.failures.first() {
+        bail!("Failed to read secret '{}': {}", id, err);
+    }
+    for (name, (version_id, data)) in initial.secrets {
+        state.record_fetched(&name, metadata.get(&name), version_id, data);
+    }
+    for output in outputs {
+        output.write(&state.merged())?;
+    }
+    eprintln!(
+        "watching {} secret(s) every {}s",
+        names.len(),
+        interval.as_secs()
+    );
+
+    loop {
+        tokio::time::sleep(interval).await;
+        let metadata = describe(client, &names).await?;
+        let changed = state.changed(&metadata);
+        if changed.is_empty() {
+            continue;
+        }
+        let mut events = refresh(client, &mut state, &changed, &metadata).await?;
+        if events.is_empty() {
+            continue;
+        }
+
+        publish(outputs, &state.merged(), hook, &mut events).await;
+        let mut stdout = io::stdout().lock();
+        for event in &events {
+            write_event(&mut stdout, event, format)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn metadata(name: &str, version: &str, last_changed: i64) -> (String, SecretMetadata) {
+        (
+            name.to_string(),
+            SecretMetadata {
+                name: name.to_string(),
+                current_version: Some(version.to_string()),
+                last_changed: Some(last_changed),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[test]
+    fn test_changed_compares_version_and_last_changed() {
+        let mut state = WatchState::new(vec!["a".to_string(), "b".to_string()]);
+        let first: BTreeMap<_, _> = [metadata("a", "v1", 10), metadata("b", "v1", 10)].into();
+        assert_eq!(state.changed(&first), ["a", "b"]);
+        for (name, m) in &first {
+            state.record(name, Fingerprint::from(m), BTreeMap::new());
+        }
+        assert!(state.changed(&first).is_empty());
+
+        let second: BTreeMap<_, _> = [metadata("a", "v2", 10), metadata("b", "v1", 20)].into();
+        assert_eq!(state.changed(&second), ["a", "b"]);
+
+        // A secret that could not be described is not reported as changed.
+        let partial: BTreeMap<_, _> = [metadata("a", "v1", 10)].into();
+        assert!(state.changed(&partial).is_empty());
+    }
+
+    #[test]
+    fn test_fetched_version_wins_over_described_one() {
+        let mut state = WatchState::new(vec!["a".to_string()]);
+        let described: BTreeMap<_, _> = [metadata("a", "v1", 10)].into();
+        // Rotated between DescribeSecret and GetSecretValue.
+        let fetched = Some("v2".to_string());
+        assert!(state.record_fetched("a", described.get("a"), fetched.clone(), BTreeMap::new()));
+        assert_eq!(state.fingerprints["a"].version_id, fetched);
+
+        // The next poll describes v2 and fetches it again, but v2 was
+        // already reported.
+        let rotated: BTreeMap<_, _> = [metadata("a", "v2", 20)].into();
+        assert_eq!(state.changed(&rotated), ["a"]);
+        assert!(!state.record_fetched("a", rotated.get("a"), fetched, BTreeMap::new()));
+        assert!(state.changed(&rotated).is_empty());
+    }
+
+    #[test]
+    fn test_merge_prefers_later_secrets() {
+        let mut state = WatchState::new(vec!["base".to_string(), "override".to_string()]);
+        let data = |pairs: &[(&str, &str)]| -> BTreeMap<String, Value> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), json!(v)))
+                .collect()
+        };
+        state.record(
+            "override",
+            Fingerprint::default(),
+            data(&[("host", "prod")]),
+        );
+        state.record(
+            "base",
+            Fingerprint::default(),
+            data(&[("host", "dev"), ("port", "1")]),
+        );
+        let merged = state.merged();
+        assert_eq!(merged["host"], "prod");
+        assert_eq!(merged["port"], "1");
+    }
+
+    #[tokio::test]
+    async fn test_hook_is_skipped_when_an_output_fails() {
+        let dir = std::env::temp_dir().join(format!("goldfinch-watch-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("hook-ran");
+        let hook = format!("touch '{}'", marker.display());
+        let event = || ChangeEvent {
+            event: "changed",
+            secret: "app".to_string(),
+            version_id: None,
+            last_changed: None,
+            hook_exit_code: None,
+            outputs_failed: false,
+        };
+        let merged: BTreeMap<String, Value> = [("HOST".to_string(), json!("db"))].into();
+
+        let broken = [Output::EnvFile(dir.join("missing").join("app.env"))];
+        let mut events = [event()];
+        publish(&broken, &merged, Some(&hook), &mut events).await;
+        assert!(events[0].outputs_failed);
+        assert_eq!(events[0].hook_exit_code, None);
+        assert!(!marker.exists());
+
+        let working = [Output::EnvFile(dir.join("app.env"))];
+        let mut events = [event()];
+        publish(&working, &merged, Some(&hook), &mut events).await;
+        assert!(!events[0].outputs_failed);
+        assert_eq!(events[0].hook_exit_code, Some(0));
+        assert!(marker.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_plain_event_is_one_sanitized_line() {
+        let event = ChangeEvent {
+            event: "changed",
+            secret: "evil\nchanged secret=forged".to_string(),
+            version_id: Some("v2".to_string()),
+            last_changed: Some(format_timestamp(0)),
+            hook_exit_code: Some(0),
+            outputs_failed: false,
+        };
+        let mut out = Vec::new();
+        write_event(&mut out, &event, OutputFormat::Plain).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.ends_with("version_id=v2 last_changed=1970-01-01T00:00:00Z hook_exit_code=0\n"));
+    }
+}
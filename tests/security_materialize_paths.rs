@@ -0,0 +1,85 @@
+//! Security test: `materialize` must keep every file inside the target
+//! directory, whatever the secret's key names are.
+//!
+//! Key names are chosen by whoever can call PutSecretValue. If a key were used
+//! as a path as-is, `../` segments, absolute paths or NUL bytes would let that
+//! caller write files anywhere the consuming service can write (CWE-22).
+
+#![cfg(unix)]
+
+use goldfinch::materialize::{materialize, FILE_MODE};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+const HOSTILE_KEYS: &[&str] = &[
+    "../escaped",
+    "../../escaped-twice",
+    "/tmp/absolute",
+    "nested/../../escaped-nested",
+    "..",
+    ".",
+    ".hidden",
+    "back\\slash",
+    "nul\0byte",
+    "new\nline",
+];
+
+fn scratch(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("goldfinch-{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    root
+}
+
+#[test]
+fn hostile_key_names_stay_inside_the_directory() {
+    let root = scratch("materialize-paths");
+    let dir = root.join("a").join("secrets");
+    let data: BTreeMap<String, Value> = HOSTILE_KEYS
+        .iter()
+        .enumerate()
+        .map(|(i, key)| (key.to_string(), json!(format!("value-{i}"))))
+        .collect();
+
+    let result = materialize(&dir, &data).expect("materialize must succeed");
+    assert_eq!(result.written.len(), HOSTILE_KEYS.len());
+
+    // Nothing may appear outside `dir` except the directories leading to it.
+    let outside: Vec<_> = fs::read_dir(root.join("a"))
+        .unwrap()
+        .map(|e| e.unwrap().file_name())
+        .collect();
+    assert_eq!(outside, ["secrets"], "files escaped the target directory");
+    assert!(!PathBuf::from("/tmp/absolute").exists());
+
+    for name in &result.written {
+        assert!(
+            !name.contains('/') && !name.starts_with('.'),
+            "unsafe name {name:?}"
+        );
+        let meta = fs::symlink_metadata(dir.join(name)).unwrap();
+        assert!(meta.is_file());
+        assert_eq!(meta.permissions().mode() & 0o777, FILE_MODE);
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn a_forged_manifest_cannot_delete_files_outside_the_directory() {
+    let root = scratch("materialize-manifest");
+    let dir = root.join("secrets");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(root.join("victim"), "keep").unwrap();
+    fs::write(dir.join(".goldfinch-files"), "../victim\n/etc/passwd\n").unwrap();
+
+    materialize(&dir, &BTreeMap::from([("k".to_string(), json!("v"))])).unwrap();
+    assert!(
+        root.join("victim").exists(),
+        "stale-file cleanup followed a forged path"
+    );
+
+    fs::remove_dir_all(&root).unwrap();
+}
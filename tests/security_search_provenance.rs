@@ -83,3 +83,34 @@ fn secret_name_matches_are_still_distinguishable_from_key_matches() {
         "a secret-name match has no matched key, so `key` must be absent/null: {first:?}"
     );
 }
+
+#[test]
+fn nested_paths_cannot_collide_with_dotted_keys() {
+    // Key "db.password" at the top level and key "password" nested under
+    // "db" are different values; their rendered identifiers must differ too.
+    let render = |data: Value| -> String {
+        let data: BTreeMap<String, Value> = serde_json::from_value(data).unwrap();
+        let mut secrets = BTreeMap::new();
+        secrets.insert("prod".to_string(), data);
+        let mut buf: Vec<u8> = Vec::new();
+        write_search(&mut buf, &secrets, "password", OutputFormat::Plain)
+            .expect("render must succeed");
+        String::from_utf8(buf).unwrap()
+    };
+
+    let dotted = render(json!({"db.password": "same-value"}));
+    let nested = render(json!({"db": {"password": "same-value"}}));
+    let bracketed = render(json!({"db[0]": {"password": "same-value"}}));
+    let indexed = render(json!({"db": [{"password": "same-value"}]}));
+
+    assert_eq!(nested, "prod/db.password: same-value\n");
+    assert_ne!(
+        dotted, nested,
+        "dotted key forged a nested path: {dotted:?}"
+    );
+    assert_eq!(indexed, "prod/db[0].password: same-value\n");
+    assert_ne!(
+        bracketed, indexed,
+        "bracketed key forged an index: {bracketed:?}"
+    );
+}